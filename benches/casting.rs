@@ -0,0 +1,121 @@
+//! Benchmarks for the hot paths of lighting a scene: intersecting two
+//! lines, casting rays through scenes of different sizes and building
+//! visibility polygons. Run them with `cargo bench`.
+
+use std::f32::consts::TAU;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tracy::math::{Line, Point, Scene};
+
+/// Side length of the square the random walls are scattered in
+const WORLD_SIZE: f32 = 1000.0;
+
+/// The longest a random wall can be
+const WALL_LENGTH: f32 = 20.0;
+
+/// A small LCG, so every run benchmarks the same scenes without pulling in
+/// a random number crate.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn random_scene(walls: usize) -> Scene {
+    let mut rng = Lcg(walls as u64);
+    let mut scene = Scene::new();
+    for _ in 0..walls {
+        let start: Point = (rng.next() * WORLD_SIZE, rng.next() * WORLD_SIZE).into();
+        let angle = rng.next() * TAU;
+        let length = rng.next() * WALL_LENGTH + 1.0;
+        let end = (start.x + angle.cos() * length, start.y + angle.sin() * length).into();
+        scene.add_line_no_graphics(start, end);
+    }
+    scene
+}
+
+/// `count` rays spread evenly around the middle of the world.
+fn rays(count: usize, length: f32) -> Vec<Line> {
+    let centre: Point = (WORLD_SIZE / 2.0, WORLD_SIZE / 2.0).into();
+    (0..count)
+        .map(|i| {
+            let angle = TAU * i as f32 / count as f32;
+            let end = (centre.x + angle.cos() * length, centre.y + angle.sin() * length).into();
+            Line::from_points(centre, end)
+        })
+        .collect()
+}
+
+fn intersection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("point_of_intersection");
+    let normal = Line::from_points((0.0, 0.0).into(), (10.0, 5.0).into());
+    let cases = [
+        ("normal/normal", Line::from_points((0.0, 5.0).into(), (10.0, 0.0).into())),
+        ("normal/vertical", Line::from_points((5.0, -5.0).into(), (5.0, 10.0).into())),
+        ("normal/parallel", Line::from_points((0.0, 1.0).into(), (10.0, 6.0).into())),
+        ("normal/miss", Line::from_points((20.0, 0.0).into(), (30.0, 5.0).into())),
+    ];
+    for (name, other) in &cases {
+        group.bench_function(*name, |b| b.iter(|| black_box(&normal).point_of_intersection(black_box(other))));
+    }
+
+    let vertical = Line::from_points((5.0, 0.0).into(), (5.0, 10.0).into());
+    let horizontal = Line::from_points((0.0, 5.0).into(), (10.0, 5.0).into());
+    let overlapping = Line::from_points((5.0, 5.0).into(), (5.0, 20.0).into());
+    group.bench_function("vertical/normal", |b| {
+        b.iter(|| black_box(&vertical).point_of_intersection(black_box(&horizontal)))
+    });
+    group.bench_function("vertical/vertical", |b| {
+        b.iter(|| black_box(&vertical).point_of_intersection(black_box(&overlapping)))
+    });
+    group.finish();
+}
+
+fn cast_in_scene(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cast_in_scene");
+    group.sample_size(20);
+    for walls in [10, 1_000, 100_000] {
+        let scene = random_scene(walls);
+        for ray_count in [1, 360, 3600] {
+            let rays = rays(ray_count, WORLD_SIZE / 2.0);
+            group.throughput(Throughput::Elements(ray_count as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} walls", walls), format!("{} rays", ray_count)),
+                &rays,
+                |b, rays| {
+                    b.iter(|| {
+                        for ray in rays {
+                            black_box(ray.cast_in_scene(&scene));
+                        }
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn visibility_polygon(c: &mut Criterion) {
+    let mut group = c.benchmark_group("visibility_polygon");
+    group.sample_size(10);
+    let centre: Point = (WORLD_SIZE / 2.0, WORLD_SIZE / 2.0).into();
+    // Every wall end in range gets three rays, so a hundred thousand walls
+    // takes seconds per polygon and is left out
+    for walls in [10, 1_000, 10_000] {
+        let scene = random_scene(walls);
+        for radius in [100.0, 500.0] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} walls", walls), format!("radius {}", radius)),
+                &radius,
+                |b, radius| b.iter(|| black_box(scene.visibility_polygon(centre, *radius))),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, intersection, cast_in_scene, visibility_polygon);
+criterion_main!(benches);
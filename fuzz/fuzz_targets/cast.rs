@@ -0,0 +1,45 @@
+//! Throws arbitrary floats at `Line::new` and `cast_in_scene`, looking for
+//! panics and rays that come back longer than they went out.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tracy::math::{Line, Scene};
+
+/// How far from the origin the length of casts is checked. Past this the
+/// target only looks for panics.
+const MAX_CHECKED: f32 = 1e30;
+
+fn length(line: &Line) -> f32 {
+    (line.end().x - line.start().x).hypot(line.end().y - line.start().y)
+}
+
+fn line(coords: &[f32]) -> Option<Line> {
+    // NaN is the one input lines refuse on purpose
+    if coords.iter().any(|c| c.is_nan()) {
+        return None;
+    }
+    Some(Line::new(coords[0], coords[1], coords[2], coords[3]))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let floats = data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    let mut lines = floats.chunks_exact(4);
+    let Some(ray) = lines.next().and_then(line) else { return };
+
+    let mut scene = Scene::new();
+    for wall in lines.filter_map(line) {
+        scene.add_line_no_graphics(wall.start(), wall.end());
+    }
+
+    let cast = ray.cast_in_scene(&scene);
+    // Anything can happen to lengths near the largest float, where the sums
+    // overflow, and away from the origin rounding moves the ends by a lot
+    let size = [ray.start(), ray.end()].iter().map(|p| p.x.abs().max(p.y.abs())).fold(1.0, f32::max);
+    if size < MAX_CHECKED {
+        assert!(length(&cast) <= length(&ray) + size * 1e-4, "{:?} became {:?}", ray, cast);
+    }
+});
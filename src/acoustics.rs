@@ -0,0 +1,238 @@
+//! Rays as sound instead of light, for working out early reflections.
+//!
+//! A source sends out rays evenly in every direction, which bounce off
+//! walls like a mirror, losing the material's `absorption` every time.
+//! Whenever a ray goes through a receiver it arrives there, so the
+//! arrivals at a receiver make up its impulse response: how loud a click
+//! at the source is at the receiver over time. The further away a
+//! receiver is the fewer rays go through it, which is how sound gets
+//! quieter with distance (in 2D, in proportion to the distance).
+
+use std::{
+    f32::consts::TAU,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use crate::{
+    math::{Line, Point, Scene},
+    stats::Stats,
+};
+
+/// In metres per second, through air at room temperature
+pub const SPEED_OF_SOUND: f32 = 343.0;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AcousticSettings {
+    /// How many rays the source sends out
+    pub rays: usize,
+    /// How many times a ray can bounce off walls before it is stopped
+    pub reflections: usize,
+    /// How long to follow rays for, in seconds
+    pub duration: f32,
+    /// How many scene units make a metre
+    pub units_per_metre: f32,
+}
+
+impl Default for AcousticSettings {
+    fn default() -> Self {
+        Self {
+            rays: 8192,
+            reflections: 16,
+            duration: 0.5,
+            units_per_metre: 100.0,
+        }
+    }
+}
+
+/// Somewhere listening, as a circle rays arrive at by going through it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Receiver {
+    pub position: Point,
+    pub radius: f32,
+}
+
+impl Receiver {
+    pub fn new(position: Point, radius: f32) -> Self {
+        Self { position, radius }
+    }
+}
+
+/// Sound getting to a receiver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arrival {
+    /// In seconds after the sound left the source
+    pub time: f32,
+    /// The share of what the source sent out
+    pub energy: f32,
+    /// How many walls it bounced off on the way
+    pub reflections: usize,
+}
+
+/// Everything that got to one receiver, earliest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImpulseResponse {
+    pub arrivals: Vec<Arrival>,
+}
+
+impl ImpulseResponse {
+    pub fn total_energy(&self) -> f32 {
+        self.arrivals.iter().map(|arrival| arrival.energy).sum()
+    }
+
+    /// The response as sound pressure at `sample_rate`, lasting `duration`
+    /// seconds. The energy arriving within each sample is added up, and the
+    /// pressure is its square root.
+    pub fn samples(&self, sample_rate: u32, duration: f32) -> Vec<f32> {
+        let mut samples = vec![0.0; sample_count(sample_rate, duration)];
+        for arrival in &self.arrivals {
+            if let Some(sample) = samples.get_mut((arrival.time * sample_rate as f32) as usize) {
+                *sample += arrival.energy;
+            }
+        }
+        samples.iter().map(|energy| energy.sqrt()).collect()
+    }
+}
+
+/// How many samples `duration` seconds is at `sample_rate`.
+fn sample_count(sample_rate: u32, duration: f32) -> usize {
+    (duration as f64 * sample_rate as f64).ceil() as usize
+}
+
+/// Follows sound from `source` round `scene`, giving the impulse response
+/// at each of `receivers`.
+pub fn impulse_responses(
+    scene: &Scene,
+    source: Point,
+    receivers: &[Receiver],
+    settings: &AcousticSettings,
+    stats: &mut Stats,
+) -> Vec<ImpulseResponse> {
+    let start = Instant::now();
+    let mut responses = vec![ImpulseResponse::default(); receivers.len()];
+    let speed = SPEED_OF_SOUND * settings.units_per_metre;
+    let reach = speed * settings.duration;
+
+    for ray in 0..settings.rays {
+        let angle = TAU * ray as f32 / settings.rays as f32;
+        let mut direction = Point::from((angle.cos(), angle.sin()));
+        let mut from = source;
+        let mut energy = 1.0 / settings.rays as f32;
+        let mut travelled = 0.0;
+        let mut passed = None;
+
+        for reflections in 0..=settings.reflections {
+            let left = reach - travelled;
+            let to = from + (direction.x * left, direction.y * left).into();
+            let (cast, hit) = Line::from_points(from, to).cast_past(scene, passed, stats);
+            let end = cast.end();
+            let length = (end.x - from.x).hypot(end.y - from.y);
+
+            for (receiver, response) in receivers.iter().zip(&mut responses) {
+                if let Some(along) = passes_through(receiver, from, direction, length) {
+                    response.arrivals.push(Arrival {
+                        time: (travelled + along) / speed,
+                        energy,
+                        reflections,
+                    });
+                }
+            }
+
+            travelled += length;
+            let Some(wall) = hit else { break };
+            let material = scene.material(scene.wall_material(wall).unwrap());
+            energy *= 1.0 - material.absorption;
+            if energy <= 0.0 {
+                break;
+            }
+            // A wall with no length has no side to bounce off
+            let Some(normal) = scene.wall(wall).unwrap().unit_normal() else { break };
+            direction = reflect(direction, normal);
+            from = end;
+            passed = Some(wall);
+        }
+    }
+
+    for response in &mut responses {
+        response.arrivals.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+    stats.record("acoustics", start.elapsed());
+    responses
+}
+
+/// How far along a ray from `from` in `direction` it gets closest to the
+/// middle of `receiver`, if that is within `length` and inside it.
+fn passes_through(receiver: &Receiver, from: Point, direction: Point, length: f32) -> Option<f32> {
+    let to_receiver = receiver.position - from;
+    let along = to_receiver.x * direction.x + to_receiver.y * direction.y;
+    if !(0.0..=length).contains(&along) {
+        return None;
+    }
+    let closest = from + (direction.x * along, direction.y * along).into();
+    let miss = (receiver.position.x - closest.x).hypot(receiver.position.y - closest.y);
+    (miss <= receiver.radius).then_some(along)
+}
+
+/// `direction` bounced like a mirror off a wall with `normal`.
+fn reflect(direction: Point, normal: Point) -> Point {
+    let d = 2.0 * (direction.x * normal.x + direction.y * normal.y);
+    (direction.x - normal.x * d, direction.y - normal.y * d).into()
+}
+
+/// Writes `responses` as a 16 bit WAV file, with one channel for each of
+/// them. They are all scaled by the same amount so the loudest sample is
+/// as loud as it can be, which keeps how loud they are next to each other.
+pub fn write_wav(path: impl AsRef<Path>, responses: &[ImpulseResponse], sample_rate: u32, duration: f32) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_wav_to(&mut file, responses, sample_rate, duration)?;
+    file.flush()
+}
+
+/// Like [`write_wav`], but to anything that can be written to.
+pub fn write_wav_to(
+    out: &mut impl Write,
+    responses: &[ImpulseResponse],
+    sample_rate: u32,
+    duration: f32,
+) -> io::Result<()> {
+    // Checked before making the samples, which could be far too many to
+    // fit in memory
+    let length = sample_count(sample_rate, duration);
+    let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "too much sound for one WAV file");
+    let channel_count = u16::try_from(responses.len()).map_err(|_| too_big())?;
+    let block = 2 * channel_count as u32;
+    let data = u32::try_from(length)
+        .ok()
+        .and_then(|length| length.checked_mul(block))
+        .filter(|data| *data <= u32::MAX - 36)
+        .ok_or_else(too_big)?;
+
+    let channels = responses.iter().map(|response| response.samples(sample_rate, duration)).collect::<Vec<_>>();
+    let peak = channels.iter().flatten().fold(0.0f32, |peak, &sample| peak.max(sample));
+    let scale = if peak > 0.0 { i16::MAX as f32 / peak } else { 0.0 };
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // Plain PCM
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&channel_count.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    let bytes_per_second = sample_rate.checked_mul(block).ok_or_else(too_big)?;
+    out.write_all(&bytes_per_second.to_le_bytes())?;
+    out.write_all(&(block as u16).to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data.to_le_bytes())?;
+    for i in 0..length {
+        for channel in &channels {
+            out.write_all(&((channel[i] * scale).round() as i16).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
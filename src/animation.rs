@@ -0,0 +1,173 @@
+//! Stepping a timeline headlessly and writing the frames out, either as
+//! numbered PNGs or as an animated GIF.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bounce::BounceSettings,
+    camera::Camera,
+    light::Light,
+    math::{Point, Scene},
+    random::Rng,
+    render::{self, Accumulator, Canvas},
+    stats::Stats,
+    timeline::{Easing, Keyframe, Timeline, Track},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: u32,
+    pub fps: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Light bouncing off walls, if it should be drawn
+    pub bounces: Option<BounceSettings>,
+    /// How many passes with different random numbers are averaged for
+    /// every frame
+    pub spp: u32,
+    /// Where the random numbers start, so the same seed always gives the
+    /// same frames
+    pub seed: u64,
+}
+
+impl Animation {
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 / self.fps
+    }
+
+    /// The time of frame `n`, in seconds.
+    pub fn time_of(&self, n: u32) -> f32 {
+        n as f32 / self.fps
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// A directory to write `frame_0000.png`, `frame_0001.png`, ... into
+    Pngs(PathBuf),
+    /// A single PNG, which only holds one frame
+    Png(PathBuf),
+    Gif(PathBuf),
+}
+
+impl Output {
+    /// Picks a GIF for paths ending in `.gif`, a single PNG for paths
+    /// ending in `.png` and a directory of PNGs for anything else.
+    pub fn from_path(path: impl Into<PathBuf>) -> Output {
+        let path = path.into();
+        let extension = path.extension().map(|ext| ext.to_ascii_lowercase());
+        match extension.as_ref().and_then(|ext| ext.to_str()) {
+            Some("gif") => Output::Gif(path),
+            Some("png") => Output::Png(path),
+            _ => Output::Pngs(path),
+        }
+    }
+}
+
+/// Replaces the position keyframes of the light at `light` with ones that
+/// move it through `points`, spread evenly over `duration` seconds.
+pub fn script_light_path(timeline: &mut Timeline, light: usize, points: &[Point], duration: f32) {
+    let track = &mut timeline.light_tracks(light).position;
+    *track = Track::new();
+    let segments = points.len().saturating_sub(1).max(1) as f32;
+    for (i, point) in points.iter().enumerate() {
+        track.insert(Keyframe {
+            time: duration * i as f32 / segments,
+            value: *point,
+            easing: Easing::Linear,
+        });
+    }
+}
+
+/// Renders every frame of the animation and hands it to `frame`. Gives
+/// the highest noise estimate of any frame, if there was anything random
+/// to estimate it for.
+pub fn render_frames(
+    scene: &mut Scene,
+    lights: &mut [Light],
+    timeline: &Timeline,
+    animation: &Animation,
+    mut frame: impl FnMut(u32, Canvas) -> io::Result<()>,
+) -> io::Result<Option<f32>> {
+    let camera = Camera::new(animation.width, animation.height);
+    let mut rng = Rng::new(animation.seed);
+    let mut noise: Option<f32> = None;
+    for n in 0..animation.frames {
+        timeline.evaluate(animation.time_of(n), scene, lights);
+        let canvas = render::render(scene, lights, &camera, animation.width, animation.height);
+        let Some(settings) = &animation.bounces else {
+            frame(n, canvas)?;
+            continue;
+        };
+
+        let mut accumulator = Accumulator::new(animation.width, animation.height);
+        for _ in 0..animation.spp.max(1) {
+            let mut pass = canvas.clone();
+            render::draw_bounces(&mut pass, scene, lights, &camera, settings, &mut rng, &mut Stats::new());
+            accumulator.add(&pass);
+        }
+        if let Some(frame_noise) = accumulator.noise() {
+            noise = Some(noise.map_or(frame_noise, |noise| noise.max(frame_noise)));
+        }
+        frame(n, accumulator.average())?;
+    }
+    Ok(noise)
+}
+
+pub fn write_animation(
+    scene: &mut Scene,
+    lights: &mut [Light],
+    timeline: &Timeline,
+    animation: &Animation,
+    output: &Output,
+) -> io::Result<Option<f32>> {
+    match output {
+        Output::Pngs(dir) => {
+            fs::create_dir_all(dir)?;
+            render_frames(scene, lights, timeline, animation, |n, canvas| {
+                canvas.write_png(frame_path(dir, n))
+            })
+        }
+        Output::Png(path) => {
+            if animation.frames != 1 {
+                let message = "a single PNG only holds one frame";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+            render_frames(scene, lights, timeline, animation, |_, canvas| canvas.write_png(path))
+        }
+        Output::Gif(path) => {
+            let file = BufWriter::new(File::create(path)?);
+            let width = to_gif_size(animation.width)?;
+            let height = to_gif_size(animation.height)?;
+            let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(gif_error)?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+
+            // GIF delays are in hundredths of a second
+            let delay = (100.0 / animation.fps).round().max(1.0) as u16;
+            render_frames(scene, lights, timeline, animation, |_, canvas| {
+                let mut frame = gif::Frame::from_rgb_speed(width, height, &canvas.to_rgb8(), 10);
+                frame.delay = delay;
+                encoder.write_frame(&frame).map_err(gif_error)
+            })
+        }
+    }
+}
+
+pub fn frame_path(dir: &Path, n: u32) -> PathBuf {
+    dir.join(format!("frame_{:04}.png", n))
+}
+
+fn to_gif_size(size: u32) -> io::Result<u16> {
+    u16::try_from(size).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "GIFs can be at most 65535 pixels across"))
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
@@ -0,0 +1,124 @@
+/// A handle into an [`Arena`]. The generation makes sure a handle to a
+/// removed item can not be used to reach whatever was put in its slot
+/// afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Index {
+    slot: u32,
+    generation: u32,
+}
+
+impl Index {
+    /// Where the item is in the arena, which is given to the next item
+    /// added after it is removed.
+    pub fn slot(self) -> usize {
+        self.slot as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A vector with stable handles that survive other items being removed.
+#[derive(Debug, Clone)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> Index {
+        self.len += 1;
+        if let Some(slot) = self.free.pop() {
+            let entry = &mut self.slots[slot as usize];
+            entry.value = Some(value);
+            Index {
+                slot,
+                generation: entry.generation,
+            }
+        } else {
+            self.slots.push(Slot {
+                generation: 0,
+                value: Some(value),
+            });
+            Index {
+                slot: self.slots.len() as u32 - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: Index) -> Option<T> {
+        let entry = self.slots.get_mut(index.slot as usize)?;
+        if entry.generation != index.generation {
+            return None;
+        }
+
+        let value = entry.value.take()?;
+        entry.generation += 1;
+        self.free.push(index.slot);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, index: Index) -> Option<&T> {
+        let entry = self.slots.get(index.slot as usize)?;
+        if entry.generation != index.generation {
+            return None;
+        }
+        entry.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
+        let entry = self.slots.get_mut(index.slot as usize)?;
+        if entry.generation != index.generation {
+            return None;
+        }
+        entry.value.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Index, &T)> {
+        self.slots.iter().enumerate().filter_map(|(slot, entry)| {
+            let index = Index {
+                slot: slot as u32,
+                generation: entry.generation,
+            };
+            entry.value.as_ref().map(|value| (index, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Index, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(slot, entry)| {
+            let index = Index {
+                slot: slot as u32,
+                generation: entry.generation,
+            };
+            entry.value.as_mut().map(|value| (index, value))
+        })
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,158 @@
+//! Light bouncing off walls, so that rooms round a corner from a light are
+//! not left pitch black.
+//!
+//! This is Monte Carlo instant radiosity: every light sends out random
+//! paths, and wherever one hits a wall it leaves behind a dim light facing
+//! away from the wall, standing in for the light the wall reflects. The
+//! path then goes on off the wall in a random direction for the next
+//! bounce. With few paths the bounced light is blotchy, and averaging
+//! passes with different random numbers evens it out, which is what
+//! [`crate::render::Accumulator`] is for.
+
+use std::f32::consts::PI;
+
+use crate::{
+    colour::Colour,
+    light::Light,
+    math::{Line, Point, Scene, MAX_GLASS},
+    random::Rng,
+    render::{falloff, RADIUS_MOD},
+    stats::Stats,
+};
+
+pub const DEFAULT_BOUNCES: usize = 2;
+pub const DEFAULT_SAMPLES: usize = 256;
+
+/// How far off a wall the light standing in for it goes, so that it does
+/// not light up the wall it is on
+const OFF_WALL: f32 = 0.5;
+
+/// The shallowest angle light is treated as hitting a wall at, as the
+/// cosine of the angle away from straight on
+const MIN_GRAZING: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BounceSettings {
+    /// How many times light bounces off walls, with 0 meaning it does not
+    pub bounces: usize,
+    /// How many paths every light sends out in every pass. Averaging
+    /// more passes gives the same as more paths in one, but shows how
+    /// noisy the result still is
+    pub samples: usize,
+}
+
+impl Default for BounceSettings {
+    fn default() -> Self {
+        Self {
+            bounces: DEFAULT_BOUNCES,
+            samples: DEFAULT_SAMPLES,
+        }
+    }
+}
+
+/// The lights standing in for the light `light` bounces off walls, from
+/// `paths` random paths that each bounce up to `bounces` times. Each one
+/// stands for the stretch of wall its path lit, so that somewhere with lit
+/// walls all around it is about as bright as the walls are lit, times
+/// their albedo.
+pub fn bounce_lights(
+    scene: &Scene,
+    light: &Light,
+    bounces: usize,
+    paths: usize,
+    rng: &mut Rng,
+    stats: &mut Stats,
+) -> Vec<Light> {
+    let mut lights = Vec::new();
+    if light.radius() <= 0.0 || paths == 0 {
+        return lights;
+    }
+    let reach = light.radius() * RADIUS_MOD;
+
+    for _ in 0..paths {
+        let angle = light.direction() + (rng.next_f32() - 0.5) * light.cone();
+        let mut direction = Point::from((angle.cos(), angle.sin()));
+        let mut from = light.position();
+        let mut colour = light.colour() * light.cone_falloff(direction);
+        // The angle the path stands for
+        let mut spread = light.cone() / paths as f32;
+
+        for _ in 0..bounces {
+            let Some(hit) = hit_wall(scene, from, direction, reach, stats) else {
+                break;
+            };
+
+            // Facing back the way the light came
+            let mut normal = hit.normal;
+            let mut cos = normal.x * direction.x + normal.y * direction.y;
+            if cos > 0.0 {
+                normal = (-normal.x, -normal.y).into();
+                cos = -cos;
+            }
+            // A path only just grazing a wall stands for a long stretch of
+            // it, and a few of those would leave bright blotches
+            let lit = spread * hit.distance / (-cos).max(MIN_GRAZING);
+            // A wall only fills half of what can be seen from in front of
+            // it
+            let share = lit / (2.0 * reach);
+            colour = colour * hit.tint * hit.albedo * (falloff(hit.distance, light.radius()) * share);
+            if colour == Colour::BLACK {
+                break;
+            }
+
+            let facing = normal.y.atan2(normal.x);
+            from = hit.point + (normal.x * OFF_WALL, normal.y * OFF_WALL).into();
+            let mut bounced = Light::spot(from, light.radius(), facing, PI);
+            bounced.set_colour(colour);
+            lights.push(bounced);
+
+            // On off the wall, standing for all of the light it reflects
+            let angle = facing + (rng.next_f32() - 0.5) * PI;
+            direction = (angle.cos(), angle.sin()).into();
+            spread = PI;
+        }
+    }
+    lights
+}
+
+struct Hit {
+    point: Point,
+    distance: f32,
+    normal: Point,
+    /// What the glass on the way multiplied the light by
+    tint: Colour,
+    albedo: Colour,
+}
+
+/// The first wall light going from `from` in `direction` bounces off,
+/// going through glass on the way. Walls with no length stop the light
+/// without it bouncing.
+fn hit_wall(scene: &Scene, from: Point, direction: Point, reach: f32, stats: &mut Stats) -> Option<Hit> {
+    let to = from + (direction.x * reach, direction.y * reach).into();
+    let mut start = from;
+    let mut tint = Colour::WHITE;
+    let mut passed = None;
+    for _ in 0..MAX_GLASS {
+        let (cast, wall) = Line::from_points(start, to).cast_past(scene, passed, stats);
+        let wall = wall?;
+        let material = scene.material(scene.wall_material(wall)?);
+        let point = cast.end();
+        match material.tint {
+            Some(glass) => {
+                tint = tint * glass;
+                start = point;
+                passed = Some(wall);
+            }
+            None => {
+                return Some(Hit {
+                    point,
+                    distance: (point.x - from.x).hypot(point.y - from.y),
+                    normal: scene.wall(wall)?.unit_normal()?,
+                    tint,
+                    albedo: material.albedo,
+                });
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,104 @@
+//! Light bouncing off walls in the viewer. Following enough paths for it to
+//! look smooth takes far too long for one frame, so every frame does one
+//! pass with a few paths and averages it with the passes before, until
+//! there have been enough passes or anything in view changes and it starts
+//! again.
+
+use sfml::{
+    graphics::{BlendMode, Image, RenderStates, RenderTarget, RenderWindow, Sprite, Texture, View},
+    SfBox,
+};
+use tracy::{
+    bounce::BounceSettings,
+    camera::Camera,
+    light::Light,
+    math::{Line, Scene},
+    random::Rng,
+    render::{self, Accumulator, Canvas},
+    stats::Stats,
+};
+
+/// How many paths every light sends out each frame, unless `--samples`
+/// says otherwise
+pub const PATHS_PER_FRAME: usize = 8;
+
+/// How many passes are averaged, unless `--spp` says otherwise
+pub const PASSES: u32 = 32;
+
+pub struct BounceView {
+    pub settings: BounceSettings,
+    /// How many passes to average before stopping
+    pub spp: u32,
+    pub enabled: bool,
+    accumulator: Accumulator,
+    rng: Rng,
+    /// What was in view when the accumulator was last reset
+    seen: Option<(Vec<Light>, Vec<Line>, Camera)>,
+    texture: Option<SfBox<Texture>>,
+}
+
+impl BounceView {
+    pub fn new(settings: BounceSettings, spp: u32, enabled: bool) -> Self {
+        Self {
+            settings,
+            spp,
+            enabled,
+            accumulator: Accumulator::new(0, 0),
+            rng: Rng::new(0),
+            seen: None,
+            texture: None,
+        }
+    }
+
+    /// How many passes have been averaged so far.
+    pub fn passes(&self) -> u32 {
+        self.accumulator.count()
+    }
+
+    /// How noisy the average still is, see [`Accumulator::noise`].
+    pub fn noise(&self) -> Option<f32> {
+        self.accumulator.noise()
+    }
+
+    /// Does another pass, unless there have been enough, starting again
+    /// if anything in view has changed since the last one.
+    pub fn update(&mut self, scene: &Scene, lights: &[Light], camera: &Camera, size: (u32, u32), stats: &mut Stats) {
+        if !self.enabled {
+            return;
+        }
+        let seen = (lights.to_vec(), scene.walls().cloned().collect::<Vec<_>>(), camera.clone());
+        let resized = (self.accumulator.width(), self.accumulator.height()) != size;
+        if resized || self.seen.as_ref() != Some(&seen) {
+            self.accumulator = Accumulator::new(size.0, size.1);
+            self.seen = Some(seen);
+        }
+        if self.passes() >= self.spp {
+            return;
+        }
+
+        let mut canvas = Canvas::new(size.0, size.1);
+        let BounceSettings { bounces, samples } = self.settings;
+        let triangles = render::bounce_triangles(scene, lights, bounces, samples, &mut self.rng, stats);
+        canvas.add_triangles(&triangles, camera);
+        self.accumulator.add(&canvas);
+
+        let average = self.accumulator.average();
+        self.texture = Image::create_from_pixels(size.0, size.1, &average.to_rgba8())
+            .and_then(|image| Texture::from_image(&image));
+    }
+
+    /// Adds the bounced light so far on top of what is in the window.
+    pub fn draw(&self, window: &mut RenderWindow) {
+        let Some(texture) = self.texture.as_ref().filter(|_| self.enabled) else {
+            return;
+        };
+        let size = window.size();
+        window.set_view(&View::new(
+            (size.x as f32 / 2.0, size.y as f32 / 2.0).into(),
+            (size.x as f32, size.y as f32).into(),
+        ));
+        let mut states = RenderStates::default();
+        states.set_blend_mode(BlendMode::ADD);
+        window.draw_with_renderstates(&Sprite::with_texture(texture), &states);
+    }
+}
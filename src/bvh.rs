@@ -0,0 +1,227 @@
+use crate::math::Bounds;
+
+const NULL: usize = usize::MAX;
+
+/// How much the bounds of every leaf are padded by, so that walls which
+/// only move a little do not have to be moved around in the tree.
+const FAT_MARGIN: f32 = 2.0;
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    bounds: Bounds,
+    parent: usize,
+    left: usize,
+    right: usize,
+    item: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn is_leaf(&self) -> bool {
+        self.left == NULL
+    }
+}
+
+/// A dynamic bounding volume hierarchy. Leaves can be added, removed and
+/// moved one at a time, and only the path from a changed leaf up to the
+/// root gets refitted.
+#[derive(Debug, Clone)]
+pub struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: usize,
+}
+
+impl<T: Copy> Bvh<T> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NULL,
+        }
+    }
+
+    /// Adds an item, returning the id of its leaf.
+    pub fn insert(&mut self, bounds: Bounds, item: T) -> usize {
+        let leaf = self.allocate(Node {
+            bounds: bounds.grow(FAT_MARGIN),
+            parent: NULL,
+            left: NULL,
+            right: NULL,
+            item: Some(item),
+        });
+        self.insert_leaf(leaf);
+        leaf
+    }
+
+    /// Removes a leaf, returning false if there was no such leaf, like one
+    /// that has already been removed.
+    pub fn remove(&mut self, leaf: usize) -> bool {
+        if !self.is_live_leaf(leaf) {
+            return false;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf].item = None;
+        self.free.push(leaf);
+        true
+    }
+
+    /// Moves a leaf to new bounds. Returns true if the leaf had moved out
+    /// of its padded bounds and so had to be put somewhere else in the tree.
+    pub fn update(&mut self, leaf: usize, bounds: Bounds) -> bool {
+        assert!(self.is_live_leaf(leaf), "leaf {} is not in the tree", leaf);
+        if self.nodes[leaf].bounds.contains(&bounds) {
+            return false;
+        }
+
+        self.remove_leaf(leaf);
+        self.nodes[leaf].bounds = bounds.grow(FAT_MARGIN);
+        self.insert_leaf(leaf);
+        true
+    }
+
+    /// Walks every node whose bounds pass `overlaps`, calling `visit` on the
+    /// items of the leaves reached. Returns how many nodes were looked at.
+    pub fn visit(&self, mut overlaps: impl FnMut(&Bounds) -> bool, mut visit: impl FnMut(T)) -> usize {
+        if self.root == NULL {
+            return 0;
+        }
+
+        let mut visited = 0;
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            visited += 1;
+            let node = &self.nodes[index];
+            if !overlaps(&node.bounds) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                if let Some(item) = node.item {
+                    visit(item);
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+        visited
+    }
+
+    /// Only leaves that are in the tree have an item, since removing one
+    /// takes it away and inner nodes never have one.
+    fn is_live_leaf(&self, leaf: usize) -> bool {
+        self.nodes.get(leaf).is_some_and(|node| node.is_leaf() && node.item.is_some())
+    }
+
+    fn allocate(&mut self, node: Node<T>) -> usize {
+        if let Some(index) = self.free.pop() {
+            self.nodes[index] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return;
+        }
+
+        // Walk down to the sibling that grows the tree the least, using the
+        // perimeter as the cost like a surface area heuristic would in 3D.
+        let leaf_bounds = self.nodes[leaf].bounds;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+            let perimeter = node.bounds.perimeter();
+            let combined = node.bounds.union(&leaf_bounds).perimeter();
+
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - perimeter);
+
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let grown = child.bounds.union(&leaf_bounds).perimeter();
+                if child.is_leaf() {
+                    grown + inheritance
+                } else {
+                    grown - child.bounds.perimeter() + inheritance
+                }
+            };
+            let left_cost = child_cost(node.left);
+            let right_cost = child_cost(node.right);
+
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            index = if left_cost < right_cost { node.left } else { node.right };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(Node {
+            bounds: self.nodes[sibling].bounds.union(&leaf_bounds),
+            parent: old_parent,
+            left: sibling,
+            right: leaf,
+            item: None,
+        });
+
+        if old_parent == NULL {
+            self.root = new_parent;
+        } else if self.nodes[old_parent].left == sibling {
+            self.nodes[old_parent].left = new_parent;
+        } else {
+            self.nodes[old_parent].right = new_parent;
+        }
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+
+        self.refit_from(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf {
+            self.nodes[parent].right
+        } else {
+            self.nodes[parent].left
+        };
+
+        if grandparent == NULL {
+            self.root = sibling;
+        } else if self.nodes[grandparent].left == parent {
+            self.nodes[grandparent].left = sibling;
+        } else {
+            self.nodes[grandparent].right = sibling;
+        }
+        self.nodes[sibling].parent = grandparent;
+        self.free.push(parent);
+
+        self.refit_from(grandparent);
+    }
+
+    fn refit_from(&mut self, mut index: usize) {
+        while index != NULL {
+            let node = &self.nodes[index];
+            let bounds = self.nodes[node.left].bounds.union(&self.nodes[node.right].bounds);
+            self.nodes[index].bounds = bounds;
+            index = self.nodes[index].parent;
+        }
+    }
+}
+
+impl<T: Copy> Default for Bvh<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
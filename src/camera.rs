@@ -0,0 +1,90 @@
+use sfml::{graphics::View, SfBox};
+
+use crate::math::Point;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 50.0;
+
+/// The view transform between world space (where the scene lives) and
+/// screen space (window pixels, origin in the upper left).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// The world point shown in the middle of the window
+    centre: Point,
+    /// Screen pixels per world unit
+    zoom: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Camera {
+    /// Creates a camera where world coordinates line up with the pixels
+    /// of a `width` by `height` window.
+    pub fn new(width: u32, height: u32) -> Self {
+        let width = width as f32;
+        let height = height as f32;
+        Self {
+            centre: (width / 2.0, height / 2.0).into(),
+            zoom: 1.0,
+            width,
+            height,
+        }
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn screen_to_world(&self, screen: Point) -> Point {
+        (
+            (screen.x - self.width / 2.0) / self.zoom + self.centre.x,
+            (screen.y - self.height / 2.0) / self.zoom + self.centre.y,
+        )
+            .into()
+    }
+
+    pub fn world_to_screen(&self, world: Point) -> Point {
+        (
+            (world.x - self.centre.x) * self.zoom + self.width / 2.0,
+            (world.y - self.centre.y) * self.zoom + self.height / 2.0,
+        )
+            .into()
+    }
+
+    /// Moves the view so that the world follows a drag of `dx`, `dy`
+    /// screen pixels.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.centre = (
+            self.centre.x - dx / self.zoom,
+            self.centre.y - dy / self.zoom,
+        )
+            .into();
+    }
+
+    /// Zooms by `factor` while keeping the world point under `screen`
+    /// in the same place on screen.
+    pub fn zoom_at(&mut self, screen: Point, factor: f32) {
+        let anchor = self.screen_to_world(screen);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let moved = self.screen_to_world(screen);
+        self.centre = (
+            self.centre.x + anchor.x - moved.x,
+            self.centre.y + anchor.y - moved.y,
+        )
+            .into();
+    }
+
+    /// Keeps the centre and zoom when the window changes size, so that
+    /// resizing shows more or less of the world instead of stretching it.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width as f32;
+        self.height = height as f32;
+    }
+
+    pub fn view(&self) -> SfBox<View> {
+        View::new(
+            self.centre.into(),
+            (self.width / self.zoom, self.height / self.zoom).into(),
+        )
+    }
+}
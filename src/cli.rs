@@ -0,0 +1,326 @@
+//! Command line handling for the headless commands of the binary.
+
+use std::{collections::HashMap, fs, str::FromStr};
+
+use tracy::{
+    acoustics::{self, AcousticSettings, Receiver, DEFAULT_SAMPLE_RATE},
+    animation::{self, Animation, Output},
+    bounce::{BounceSettings, DEFAULT_BOUNCES, DEFAULT_SAMPLES},
+    import::bitmap::{self, BitmapOptions},
+    light::{Light, DEFAULT_RADIUS},
+    math::Point,
+    render,
+    scene_file::SceneFile,
+    stats::Stats,
+    svg::{LightStyle, SvgOptions, MAX_GRID_LINES},
+};
+
+pub const USAGE: &str = "\
+Usage:
+    tracy [SCENE] [--shader FILE.frag] [--no-shader]
+          [--bounces N] [--samples N] [--spp N]
+    tracy render [SCENE] [--frames N] [--fps F] [--out DIR|FILE.gif|FILE.png]
+                 [--width W] [--height H] [--path \"X,Y X,Y ...\"]
+                 [--bounces N] [--samples N] [--spp N] [--seed S]
+    tracy export-svg [SCENE] [--out FILE.svg] [--show position|rays|visibility]
+                     [--time T] [--grid SPACING] [--labels]
+    tracy acoustics [SCENE] --receivers \"X,Y X,Y ...\" [--source X,Y]
+                    [--out FILE.wav] [--radius R] [--rays N] [--reflections N]
+                    [--duration S] [--scale UNITS_PER_METRE] [--rate HZ]
+
+Every command also takes [--threshold T] [--tolerance D] for tracing the
+walls out of a .png SCENE.";
+
+/// Flags for loading the scene, which every command takes
+const SCENE_FLAGS: &[&str] = &["threshold", "tolerance"];
+
+/// Arguments split up into positional ones, `--name value` pairs and
+/// `--switch`es.
+pub struct Flags {
+    positional: Vec<String>,
+    values: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Flags {
+    pub fn parse(args: &[String], values: &[&str], switches: &[&str]) -> Result<Flags, String> {
+        let mut flags = Flags {
+            positional: Vec::new(),
+            values: HashMap::new(),
+            switches: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if switches.contains(&name) => flags.switches.push(name.to_string()),
+                Some(name) if values.contains(&name) || SCENE_FLAGS.contains(&name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("--{} needs a value", name))?;
+                    flags.values.insert(name.to_string(), value.clone());
+                }
+                Some(name) => return Err(format!("Unknown option --{}\n\n{}", name, USAGE)),
+                None => flags.positional.push(arg.clone()),
+            }
+        }
+        Ok(flags)
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn value<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("`{}` is not a valid value for --{}", value, name)),
+            None => Ok(default),
+        }
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|s| s == name)
+    }
+}
+
+/// The scene named by the first positional argument, or an empty one.
+/// PNGs are traced with `--threshold` and `--tolerance` if they are given.
+pub fn load_scene(flags: &Flags) -> Result<SceneFile, String> {
+    let Some(path) = flags.positional(0) else {
+        return Ok(SceneFile::default());
+    };
+    if !path.to_ascii_lowercase().ends_with(".png") {
+        if SCENE_FLAGS.iter().any(|name| flags.get(name).is_some()) {
+            return Err("--threshold and --tolerance only work for .png scenes".to_string());
+        }
+        return SceneFile::load(path).map_err(|e| format!("Could not load {}: {}", path, e));
+    }
+
+    let defaults = BitmapOptions::default();
+    let options = BitmapOptions {
+        threshold: flags.value("threshold", defaults.threshold)?,
+        tolerance: flags.value("tolerance", defaults.tolerance)?,
+        ..defaults
+    };
+    if !(0.0..=1.0).contains(&options.threshold) {
+        return Err("--threshold has to be from 0 to 1".to_string());
+    }
+    if !(options.tolerance >= 0.0 && options.tolerance.is_finite()) {
+        return Err("--tolerance can not be below zero".to_string());
+    }
+    bitmap::load(path, &options)
+        .map(SceneFile::from)
+        .map_err(|e| format!("Could not load {}: {}", path, e))
+}
+
+/// Light bouncing off walls, if `--bounces` was given, following `samples`
+/// paths every pass unless `--samples` says otherwise. `--samples` and
+/// `--spp` are only allowed with `--bounces`.
+pub fn bounce_settings(flags: &Flags, samples: usize) -> Result<Option<BounceSettings>, String> {
+    if flags.get("bounces").is_none() {
+        if flags.get("samples").is_some() || flags.get("spp").is_some() {
+            return Err("--samples and --spp only work with --bounces".to_string());
+        }
+        return Ok(None);
+    }
+    let settings = BounceSettings {
+        bounces: flags.value("bounces", DEFAULT_BOUNCES)?,
+        samples: flags.value("samples", samples)?,
+    };
+    if settings.samples == 0 {
+        return Err("--samples has to be above zero".to_string());
+    }
+    Ok(Some(settings))
+}
+
+/// How many passes to average, from `--spp`.
+pub fn spp(flags: &Flags, default: u32) -> Result<u32, String> {
+    match flags.value("spp", default)? {
+        0 => Err("--spp has to be above zero".to_string()),
+        spp => Ok(spp),
+    }
+}
+
+/// Parses a list of points like `100,200 300,400`.
+fn parse_points(list: &str) -> Result<Vec<Point>, String> {
+    list.split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|point| !point.is_empty())
+        .map(|point| {
+            let (x, y) = point
+                .split_once(',')
+                .ok_or_else(|| format!("`{}` is not a point like `x,y`", point))?;
+            let x = x.trim().parse::<f32>();
+            let y = y.trim().parse::<f32>();
+            match (x, y) {
+                (Ok(x), Ok(y)) if x.is_finite() && y.is_finite() => Ok((x, y).into()),
+                _ => Err(format!("`{}` is not a point like `x,y`", point)),
+            }
+        })
+        .collect()
+}
+
+pub fn render(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(
+        args,
+        &["frames", "fps", "out", "width", "height", "path", "bounces", "samples", "spp", "seed"],
+        &[],
+    )?;
+    let mut file = load_scene(&flags)?;
+
+    let animation = Animation {
+        frames: flags.value("frames", 60)?,
+        fps: flags.value("fps", 30.0)?,
+        width: flags.value("width", 1200)?,
+        height: flags.value("height", 1200)?,
+        bounces: bounce_settings(&flags, DEFAULT_SAMPLES)?,
+        spp: spp(&flags, 1)?,
+        seed: flags.value("seed", 0)?,
+    };
+    let fps_ok = animation.fps.is_finite() && animation.fps > 0.0;
+    if animation.frames == 0 || !fps_ok || animation.width == 0 || animation.height == 0 {
+        return Err("--frames, --fps, --width and --height all have to be above zero".to_string());
+    }
+    // Every pixel ends up as four bytes somewhere on the way out
+    let bytes = render::pixel_count(animation.width, animation.height).and_then(|count| count.checked_mul(4));
+    if bytes.is_none_or(|bytes| bytes > u32::MAX as usize) {
+        return Err(format!("{}x{} is too big to render", animation.width, animation.height));
+    }
+
+    if file.lights.is_empty() {
+        let centre = (animation.width as f32 / 2.0, animation.height as f32 / 2.0).into();
+        file.lights.push(Light::new(centre, DEFAULT_RADIUS));
+    }
+
+    if let Some(path) = flags.get("path") {
+        let points = parse_points(path)?;
+        if points.is_empty() {
+            return Err("--path needs at least one point".to_string());
+        }
+        let last_frame = animation.time_of(animation.frames - 1);
+        animation::script_light_path(&mut file.timeline, 0, &points, last_frame);
+    }
+
+    let out = flags.get("out").unwrap_or("frames");
+    let output = Output::from_path(out);
+    if matches!(output, Output::Png(_)) && animation.frames != 1 {
+        return Err(format!("{} only holds one frame, use --frames 1 or write to a directory or a GIF", out));
+    }
+    let noise = animation::write_animation(
+        &mut file.scene,
+        &mut file.lights,
+        &file.timeline,
+        &animation,
+        &output,
+    )
+    .map_err(|e| format!("Could not write {}: {}", out, e))?;
+    if let Some(noise) = noise {
+        println!("Noise after {} passes per frame: {:.4}", animation.spp, noise);
+    }
+    Ok(())
+}
+
+pub fn export_svg(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &["out", "show", "time", "grid"], &["labels"])?;
+    let mut file = load_scene(&flags)?;
+
+    let light_style = match flags.get("show").unwrap_or("visibility") {
+        "position" => LightStyle::Position,
+        "rays" => LightStyle::Rays,
+        "visibility" => LightStyle::Visibility,
+        other => return Err(format!("`{}` is not one of position, rays or visibility", other)),
+    };
+    let grid = flags.value("grid", 0.0)?;
+    let options = SvgOptions {
+        light_style,
+        grid: (grid > 0.0).then_some(grid),
+        labels: flags.switch("labels"),
+        ..Default::default()
+    };
+
+    let time = flags.value("time", 0.0)?;
+    file.timeline.evaluate(time, &mut file.scene, &mut file.lights);
+
+    if file.scene.svg_grid_lines(&file.lights, &options) > MAX_GRID_LINES {
+        return Err(format!(
+            "--grid {} would draw more than {} lines across this scene",
+            grid, MAX_GRID_LINES
+        ));
+    }
+
+    let svg = file.scene.to_svg_with(&file.lights, &options);
+    match flags.get("out") {
+        Some(out) => fs::write(out, svg).map_err(|e| format!("Could not write {}: {}", out, e)),
+        None => {
+            print!("{}", svg);
+            Ok(())
+        }
+    }
+}
+
+pub fn acoustics(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(
+        args,
+        &["receivers", "source", "out", "radius", "rays", "reflections", "duration", "scale", "rate"],
+        &[],
+    )?;
+    let file = load_scene(&flags)?;
+
+    let source = match flags.get("source") {
+        Some(source) => match parse_points(source)?[..] {
+            [source] => source,
+            _ => return Err("--source has to be one point".to_string()),
+        },
+        None => file
+            .lights
+            .first()
+            .map(Light::position)
+            .ok_or("--source is needed when the scene has no lights")?,
+    };
+    let radius = flags.value("radius", 10.0)?;
+    let receivers = parse_points(flags.get("receivers").unwrap_or(""))?
+        .into_iter()
+        .map(|position| Receiver::new(position, radius))
+        .collect::<Vec<_>>();
+    if receivers.is_empty() {
+        return Err("--receivers needs at least one point".to_string());
+    }
+
+    let defaults = AcousticSettings::default();
+    let settings = AcousticSettings {
+        rays: flags.value("rays", defaults.rays)?,
+        reflections: flags.value("reflections", defaults.reflections)?,
+        duration: flags.value("duration", defaults.duration)?,
+        units_per_metre: flags.value("scale", defaults.units_per_metre)?,
+    };
+    let rate = flags.value("rate", DEFAULT_SAMPLE_RATE)?;
+    let positive = |n: f32| n.is_finite() && n > 0.0;
+    let lengths_ok = positive(radius) && positive(settings.duration) && positive(settings.units_per_metre);
+    if settings.rays == 0 || !lengths_ok || rate == 0 {
+        return Err("--rays, --radius, --duration, --scale and --rate all have to be above zero".to_string());
+    }
+
+    let responses = acoustics::impulse_responses(&file.scene, source, &receivers, &settings, &mut Stats::new());
+    for (receiver, response) in receivers.iter().zip(&responses) {
+        match response.arrivals.first() {
+            Some(first) => println!(
+                "{},{}: {} arrivals, first after {:.1} ms, {:.5} of the energy",
+                receiver.position.x,
+                receiver.position.y,
+                response.arrivals.len(),
+                first.time * 1000.0,
+                response.total_energy()
+            ),
+            None => println!("{},{}: nothing arrived", receiver.position.x, receiver.position.y),
+        }
+    }
+
+    let out = flags.get("out").unwrap_or("impulse.wav");
+    acoustics::write_wav(out, &responses, rate, settings.duration)
+        .map_err(|e| format!("Could not write {}: {}", out, e))
+}
@@ -0,0 +1,68 @@
+use std::ops;
+
+use sfml::graphics;
+
+/// A linear RGB colour, with every channel going from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colour {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Colour {
+    pub const BLACK: Colour = Colour::new(0.0, 0.0, 0.0);
+    pub const WHITE: Colour = Colour::new(1.0, 1.0, 1.0);
+    pub const GREEN: Colour = Colour::new(0.0, 1.0, 0.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Blends `other` over this colour, with `alpha` of 1 meaning only
+    /// `other` is left.
+    pub fn blend(self, other: Colour, alpha: f32) -> Colour {
+        self * (1.0 - alpha) + other * alpha
+    }
+
+    /// How bright the colour looks, between 0 and 1 for colours in range.
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn to_rgb8(self) -> [u8; 3] {
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [channel(self.r), channel(self.g), channel(self.b)]
+    }
+}
+
+impl ops::Add for Colour {
+    type Output = Colour;
+
+    fn add(self, rhs: Colour) -> Colour {
+        Colour::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl ops::Mul for Colour {
+    type Output = Colour;
+
+    fn mul(self, rhs: Colour) -> Colour {
+        Colour::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl ops::Mul<f32> for Colour {
+    type Output = Colour;
+
+    fn mul(self, rhs: f32) -> Colour {
+        Colour::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl From<Colour> for graphics::Color {
+    fn from(colour: Colour) -> Self {
+        let [r, g, b] = colour.to_rgb8();
+        graphics::Color::rgb(r, g, b)
+    }
+}
@@ -3,11 +3,19 @@
 precision mediump float;
 layout(origin_upper_left) in vec4 gl_FragCoord;
 
+// Every uniform a light shader gets is listed in src/light_shader.rs
 uniform vec2 Centre;
 uniform vec3 Colour;
 uniform float Radius;
+// Spotlights only shine HalfCone radians either side of Direction, fading
+// out over ConeEdge radians. Lights that shine all around pass a HalfCone
+// bigger than pi.
+uniform float Direction;
+uniform float HalfCone;
+uniform float ConeEdge;
 
 #define RADIUS_MOD 0.4
+#define PI 3.14159265
 
 void main(void)
 {
@@ -15,5 +23,8 @@ void main(void)
     float y = Centre.y - gl_FragCoord.y;
     float alpha = 1.0 - sqrt(x * x + y * y) / (Radius * RADIUS_MOD);
 
+    float from_middle = abs(mod(atan(-y, -x) - Direction + PI, 2.0 * PI) - PI);
+    alpha *= clamp((HalfCone - from_middle) / ConeEdge, 0.0, 1.0);
+
     gl_FragColor = vec4(Colour, alpha);
 }
@@ -0,0 +1,5 @@
+//! Turning level outlines drawn in other tools into walls.
+
+pub mod bitmap;
+pub mod svg;
+pub mod tiled;
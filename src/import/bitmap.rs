@@ -0,0 +1,338 @@
+//! Reading walls out of bitmap masks. The image is thresholded, the outlines
+//! of the solid areas are traced with marching squares, and then simplified
+//! with Douglas-Peucker so a straight edge becomes a single wall.
+//!
+//! By default dark pixels are solid and light or transparent ones are
+//! empty. Every outline becomes a closed polygon of walls, including the
+//! ones around holes.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::math::{Point, Scene};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Png(png::DecodingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Png(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(e: png::DecodingError) -> Self {
+        Error::Png(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitmapOptions {
+    /// How solid a pixel has to be to count as a wall, between 0 and 1
+    pub threshold: f32,
+    /// Makes light and transparent pixels solid instead of dark ones
+    pub invert: bool,
+    /// How far the simplified outline may stray from the traced one, in
+    /// world units
+    pub tolerance: f32,
+    /// How many world units one pixel is across
+    pub scale: f32,
+}
+
+impl Default for BitmapOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            invert: false,
+            tolerance: 1.0,
+            scale: 1.0,
+        }
+    }
+}
+
+/// A greyscale image where 1 is as dark as possible and 0 is white or
+/// transparent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    width: u32,
+    height: u32,
+    darkness: Vec<f32>,
+}
+
+impl Mask {
+    pub fn from_fn(width: u32, height: u32, mut darkness: impl FnMut(u32, u32) -> f32) -> Self {
+        let darkness = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| darkness(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            darkness,
+        }
+    }
+
+    pub fn from_png(reader: impl Read) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = info.color_type.samples();
+        let darkness = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| {
+                let channel = |i: usize| pixel[i] as f32 / 255.0;
+                let (luma, alpha) = match info.color_type {
+                    png::ColorType::Grayscale => (channel(0), 1.0),
+                    png::ColorType::GrayscaleAlpha => (channel(0), channel(1)),
+                    png::ColorType::Rgb => (0.2126 * channel(0) + 0.7152 * channel(1) + 0.0722 * channel(2), 1.0),
+                    _ => (
+                        0.2126 * channel(0) + 0.7152 * channel(1) + 0.0722 * channel(2),
+                        channel(3),
+                    ),
+                };
+                (1.0 - luma) * alpha
+            })
+            .collect();
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            darkness,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// How dark the pixel is, with everything outside the image being empty
+    /// so that outlines touching the border are still closed.
+    fn darkness(&self, x: i64, y: i64) -> f32 {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            0.0
+        } else {
+            self.darkness[(y * self.width as i64 + x) as usize]
+        }
+    }
+}
+
+pub fn load(path: impl AsRef<Path>, options: &BitmapOptions) -> Result<Scene, Error> {
+    let mask = Mask::from_png(BufReader::new(File::open(path)?))?;
+    Ok(trace(&mask, options))
+}
+
+/// Traces the outlines of the solid parts of `mask` into a scene of closed
+/// wall polygons.
+pub fn trace(mask: &Mask, options: &BitmapOptions) -> Scene {
+    let mut scene = Scene::new();
+    for outline in contours(mask, options) {
+        let outline = outline
+            .into_iter()
+            .map(|(x, y)| Point::from((x * options.scale, y * options.scale)))
+            .collect::<Vec<_>>();
+        let polygon = simplify_closed(&outline, options.tolerance);
+        if polygon.len() < 3 {
+            continue;
+        }
+        for i in 0..polygon.len() {
+            scene.add_line_no_graphics(polygon[i], polygon[(i + 1) % polygon.len()]);
+        }
+    }
+    scene
+}
+
+/// Where an outline crosses the edge between two neighbouring samples,
+/// named by the first sample and whether the edge goes right or down.
+type Crossing = (i64, i64, bool);
+
+/// Which edges of a marching squares cell the outline crosses, for every
+/// combination of solid corners. The bits are top left, top right, bottom
+/// right and bottom left from high to low, and in the two saddle cases the
+/// solid corners are kept apart.
+const CASES: [&[(Edge, Edge)]; 16] = {
+    use Edge::*;
+    [
+        &[],
+        &[(Left, Bottom)],
+        &[(Bottom, Right)],
+        &[(Left, Right)],
+        &[(Top, Right)],
+        &[(Top, Right), (Left, Bottom)],
+        &[(Top, Bottom)],
+        &[(Left, Top)],
+        &[(Left, Top)],
+        &[(Top, Bottom)],
+        &[(Left, Top), (Bottom, Right)],
+        &[(Top, Right)],
+        &[(Left, Right)],
+        &[(Bottom, Right)],
+        &[(Left, Bottom)],
+        &[],
+    ]
+};
+
+#[derive(Debug, Clone, Copy)]
+enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// Runs marching squares over the pixel centres and joins the pieces into
+/// closed outlines, in pixel coordinates.
+fn contours(mask: &Mask, options: &BitmapOptions) -> Vec<Vec<(f32, f32)>> {
+    let solidness = |x: i64, y: i64| {
+        let darkness = mask.darkness(x, y);
+        let inside = x >= 0 && y >= 0 && x < mask.width as i64 && y < mask.height as i64;
+        if options.invert && inside {
+            1.0 - darkness
+        } else {
+            darkness
+        }
+    };
+    let solid = |x: i64, y: i64| solidness(x, y) > options.threshold;
+
+    // Every crossing is shared by exactly two cells, so each one ends up
+    // with exactly two neighbours
+    let mut neighbours: HashMap<Crossing, Vec<Crossing>> = HashMap::new();
+    for y in -1..mask.height as i64 {
+        for x in -1..mask.width as i64 {
+            let case = (solid(x, y) as usize) << 3
+                | (solid(x + 1, y) as usize) << 2
+                | (solid(x + 1, y + 1) as usize) << 1
+                | solid(x, y + 1) as usize;
+            for &(a, b) in CASES[case] {
+                let crossing = |edge| match edge {
+                    Edge::Top => (x, y, true),
+                    Edge::Right => (x + 1, y, false),
+                    Edge::Bottom => (x, y + 1, true),
+                    Edge::Left => (x, y, false),
+                };
+                neighbours.entry(crossing(a)).or_default().push(crossing(b));
+                neighbours.entry(crossing(b)).or_default().push(crossing(a));
+            }
+        }
+    }
+
+    // Puts the crossing where the solidness would be exactly the threshold
+    // if it changed linearly between the two samples
+    let position = |(x, y, right): Crossing| {
+        let (x2, y2) = if right { (x + 1, y) } else { (x, y + 1) };
+        let (a, b) = (solidness(x, y), solidness(x2, y2));
+        let t = if a == b { 0.5 } else { ((options.threshold - a) / (b - a)).clamp(0.0, 1.0) };
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+        if right {
+            (x + t, y)
+        } else {
+            (x, y + t)
+        }
+    };
+
+    // Sorted so that the same image always gives the same walls
+    let mut starts = neighbours.keys().copied().collect::<Vec<_>>();
+    starts.sort_unstable();
+
+    let mut outlines = Vec::new();
+    let mut visited = HashSet::new();
+    for start in starts {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut outline = Vec::new();
+        let (mut previous, mut current) = (None, start);
+        loop {
+            visited.insert(current);
+            outline.push(position(current));
+            let next = neighbours[&current]
+                .iter()
+                .copied()
+                .find(|next| Some(*next) != previous && !visited.contains(next));
+            match next {
+                Some(next) => {
+                    previous = Some(current);
+                    current = next;
+                }
+                None => break,
+            }
+        }
+        outlines.push(outline);
+    }
+    outlines
+}
+
+/// Simplifies a closed outline by splitting it at the first point and the
+/// point furthest from it, and simplifying both halves.
+fn simplify_closed(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let distance = |a: Point, b: Point| (b.x - a.x).hypot(b.y - a.y);
+    let far = (1..points.len())
+        .max_by(|&i, &j| distance(points[0], points[i]).total_cmp(&distance(points[0], points[j])))
+        .unwrap();
+
+    let mut first = points[..=far].to_vec();
+    let mut second = points[far..].to_vec();
+    second.push(points[0]);
+
+    first = simplify(&first, tolerance);
+    second = simplify(&second, tolerance);
+    first.pop();
+    second.pop();
+    first.extend(second);
+    first
+}
+
+/// Douglas-Peucker: keeps the point furthest from the line between the ends
+/// if it is further than `tolerance`, and repeats on both sides of it.
+fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    let (start, end) = (points[0], points[points.len() - 1]);
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = dx.hypot(dy);
+    let distance = |p: Point| {
+        if length == 0.0 {
+            (p.x - start.x).hypot(p.y - start.y)
+        } else {
+            ((p.x - start.x) * dy - (p.y - start.y) * dx).abs() / length
+        }
+    };
+
+    let furthest = (1..points.len().saturating_sub(1))
+        .max_by(|&i, &j| distance(points[i]).total_cmp(&distance(points[j])));
+    match furthest {
+        Some(i) if distance(points[i]) > tolerance => {
+            let mut left = simplify(&points[..=i], tolerance);
+            left.pop();
+            left.extend(simplify(&points[i..], tolerance));
+            left
+        }
+        _ => vec![start, end],
+    }
+}
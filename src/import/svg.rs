@@ -0,0 +1,432 @@
+//! Reading walls out of SVG files. Every outline in the drawing becomes a
+//! chain of walls: `<line>`, `<polyline>`, `<polygon>`, `<rect>`,
+//! `<circle>`, `<ellipse>` and `<path>` are understood, `transform`s are
+//! applied, and curves and arcs are flattened into short straight walls.
+//!
+//! Walls get a material named after their stroke colour (like `#ff0000`),
+//! or the default material if the shape has no stroke. Rounded corners of
+//! rects are ignored, and anything inside `<defs>`, `<clipPath>`, `<mask>`,
+//! `<pattern>` or `<symbol>` is skipped since it is not drawn by itself.
+//!
+//! Lengths in real world units like `mm` or `pt` are turned into pixels, at
+//! 96 to the inch, and relative ones (`%`, `em` and `ex`) are an error. A
+//! `viewBox` on the root `<svg>` is fitted into its `width` and `height`
+//! the way `preserveAspectRatio` says, so a scene unit is a pixel of the
+//! drawing as it would be shown. Numbers too big for an `f32` are an error
+//! as well.
+
+use std::{f64::consts::PI, fmt, fs, io, path::Path, str::FromStr};
+
+use roxmltree::{Document, Node};
+use svgtypes::{
+    Align, AspectRatio, Color, Length, LengthUnit, PointsParser, SimplePathSegment, SimplifyingPathParser,
+    TransformListParser, TransformListToken, ViewBox,
+};
+
+use crate::{
+    colour::Colour,
+    material::{Material, MaterialId},
+    math::{Point, Scene},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    Attribute {
+        line: u32,
+        element: String,
+        attribute: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Xml(e) => write!(f, "{}", e),
+            Error::Attribute {
+                line,
+                element,
+                attribute,
+                message,
+            } => write!(f, "line {}: bad `{}` on <{}>: {}", line, attribute, element, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(e: roxmltree::Error) -> Self {
+        Error::Xml(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportOptions {
+    /// How far a flattened curve may stray from the real one, in world units
+    pub tolerance: f32,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { tolerance: 0.5 }
+    }
+}
+
+pub fn load(path: impl AsRef<Path>, options: &ImportOptions) -> Result<Scene, Error> {
+    parse(&fs::read_to_string(path)?, options)
+}
+
+pub fn parse(source: &str, options: &ImportOptions) -> Result<Scene, Error> {
+    let document = Document::parse(source)?;
+    let mut importer = Importer {
+        document: &document,
+        tolerance: options.tolerance.max(0.001) as f64,
+        scene: Scene::new(),
+    };
+    let root = document.root_element();
+    importer.visit(root, importer.view_box(root)?)?;
+    Ok(importer.scene)
+}
+
+/// How many pixels one `unit` is, or `None` if it depends on
+/// something else, like a font size or the size of the parent.
+fn pixels_per(unit: LengthUnit) -> Option<f64> {
+    match unit {
+        LengthUnit::None | LengthUnit::Px => Some(1.0),
+        LengthUnit::In => Some(96.0),
+        LengthUnit::Cm => Some(96.0 / 2.54),
+        LengthUnit::Mm => Some(96.0 / 25.4),
+        LengthUnit::Pt => Some(96.0 / 72.0),
+        LengthUnit::Pc => Some(16.0),
+        LengthUnit::Em | LengthUnit::Ex | LengthUnit::Percent => None,
+    }
+}
+
+/// An affine transform laid out like SVG's `matrix(a b c d e f)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Matrix([f64; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// The transform that applies `inner` first and then this one.
+    fn then(self, inner: Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = inner.0;
+        Matrix([
+            a * a2 + c * b2,
+            b * a2 + d * b2,
+            a * c2 + c * d2,
+            b * c2 + d * d2,
+            a * e2 + c * f2 + e,
+            b * e2 + d * f2 + f,
+        ])
+    }
+
+    fn apply(self, (x, y): (f64, f64)) -> (f64, f64) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    /// Roughly how much the transform stretches lengths.
+    fn scale(self) -> f64 {
+        let [a, b, c, d, ..] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+}
+
+impl From<TransformListToken> for Matrix {
+    fn from(token: TransformListToken) -> Self {
+        match token {
+            TransformListToken::Matrix { a, b, c, d, e, f } => Matrix([a, b, c, d, e, f]),
+            TransformListToken::Translate { tx, ty } => Matrix([1.0, 0.0, 0.0, 1.0, tx, ty]),
+            TransformListToken::Scale { sx, sy } => Matrix([sx, 0.0, 0.0, sy, 0.0, 0.0]),
+            TransformListToken::Rotate { angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                Matrix([cos, sin, -sin, cos, 0.0, 0.0])
+            }
+            TransformListToken::SkewX { angle } => Matrix([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            TransformListToken::SkewY { angle } => Matrix([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+        }
+    }
+}
+
+struct Importer<'a, 'input> {
+    document: &'a Document<'input>,
+    tolerance: f64,
+    scene: Scene,
+}
+
+impl Importer<'_, '_> {
+    fn visit(&mut self, node: Node, parent: Matrix) -> Result<(), Error> {
+        let matrix = match node.attribute("transform") {
+            Some(list) => {
+                let mut matrix = parent;
+                for token in TransformListParser::from(list) {
+                    let token = token.map_err(|e| self.error(node, "transform", e))?;
+                    matrix = matrix.then(token.into());
+                }
+                matrix
+            }
+            None => parent,
+        };
+
+        match node.tag_name().name() {
+            "defs" | "clipPath" | "mask" | "pattern" | "symbol" => return Ok(()),
+            "line" => {
+                let start = (self.number(node, "x1")?, self.number(node, "y1")?);
+                let end = (self.number(node, "x2")?, self.number(node, "y2")?);
+                self.add_outline(node, matrix, &[start, end], false);
+            }
+            "polyline" | "polygon" => {
+                let points = PointsParser::from(node.attribute("points").unwrap_or_default()).collect::<Vec<_>>();
+                self.add_outline(node, matrix, &points, node.has_tag_name("polygon"));
+            }
+            "rect" => {
+                let (x, y) = (self.number(node, "x")?, self.number(node, "y")?);
+                let (width, height) = (self.number(node, "width")?, self.number(node, "height")?);
+                if width > 0.0 && height > 0.0 {
+                    let corners = [(x, y), (x + width, y), (x + width, y + height), (x, y + height)];
+                    self.add_outline(node, matrix, &corners, true);
+                }
+            }
+            "circle" => {
+                let r = self.number(node, "r")?;
+                self.add_ellipse(node, matrix, r, r)?;
+            }
+            "ellipse" => {
+                let (rx, ry) = (self.number(node, "rx")?, self.number(node, "ry")?);
+                self.add_ellipse(node, matrix, rx, ry)?;
+            }
+            "path" => self.add_path(node, matrix)?,
+            _ => {}
+        }
+
+        for child in node.children().filter(Node::is_element) {
+            self.visit(child, matrix)?;
+        }
+        Ok(())
+    }
+
+    fn add_ellipse(&mut self, node: Node, matrix: Matrix, rx: f64, ry: f64) -> Result<(), Error> {
+        if rx <= 0.0 || ry <= 0.0 {
+            return Ok(());
+        }
+        let (cx, cy) = (self.number(node, "cx")?, self.number(node, "cy")?);
+        let radius = rx.max(ry) * matrix.scale();
+        let segments = self.segments_for(radius, 2.0 * PI);
+        let points = (0..segments)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f64 / segments as f64;
+                (cx + rx * angle.cos(), cy + ry * angle.sin())
+            })
+            .collect::<Vec<_>>();
+        self.add_outline(node, matrix, &points, true);
+        Ok(())
+    }
+
+    fn add_path(&mut self, node: Node, matrix: Matrix) -> Result<(), Error> {
+        let data = node.attribute("d").unwrap_or_default();
+        let mut subpath: Vec<(f64, f64)> = Vec::new();
+        for segment in SimplifyingPathParser::from(data) {
+            let segment = segment.map_err(|e| self.error(node, "d", e))?;
+            let last = subpath.last().copied().unwrap_or_default();
+            match segment {
+                SimplePathSegment::MoveTo { x, y } => {
+                    self.add_outline(node, Matrix::IDENTITY, &subpath, false);
+                    subpath = vec![matrix.apply((x, y))];
+                }
+                SimplePathSegment::LineTo { x, y } => subpath.push(matrix.apply((x, y))),
+                SimplePathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                    // Affine transforms keep curves curves, so they can be
+                    // flattened in world space where the tolerance applies
+                    let controls = [last, matrix.apply((x1, y1)), matrix.apply((x2, y2)), matrix.apply((x, y))];
+                    self.flatten_cubic(controls, &mut subpath, 0);
+                }
+                SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                    let control = matrix.apply((x1, y1));
+                    let end = matrix.apply((x, y));
+                    let lerp = |a: (f64, f64), b: (f64, f64)| (a.0 + (b.0 - a.0) * 2.0 / 3.0, a.1 + (b.1 - a.1) * 2.0 / 3.0);
+                    self.flatten_cubic([last, lerp(last, control), lerp(end, control), end], &mut subpath, 0);
+                }
+                SimplePathSegment::ClosePath => {
+                    if let Some(&first) = subpath.first() {
+                        subpath.push(first);
+                    }
+                    self.add_outline(node, Matrix::IDENTITY, &subpath, false);
+                    subpath = subpath.first().copied().into_iter().collect();
+                }
+            }
+        }
+        self.add_outline(node, Matrix::IDENTITY, &subpath, false);
+        Ok(())
+    }
+
+    /// Splits the curve in half until both halves are flat enough, and then
+    /// pushes the points after the first one.
+    fn flatten_cubic(&self, [p0, p1, p2, p3]: [(f64, f64); 4], points: &mut Vec<(f64, f64)>, depth: u32) {
+        let distance = |(px, py): (f64, f64)| {
+            let (dx, dy) = (p3.0 - p0.0, p3.1 - p0.1);
+            let length = dx.hypot(dy);
+            if length == 0.0 {
+                (px - p0.0).hypot(py - p0.1)
+            } else {
+                ((px - p0.0) * dy - (py - p0.1) * dx).abs() / length
+            }
+        };
+        if depth >= 16 || distance(p1).max(distance(p2)) <= self.tolerance {
+            points.push(p3);
+            return;
+        }
+
+        let mid = |a: (f64, f64), b: (f64, f64)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let (p01, p12, p23) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+        let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+        let centre = mid(p012, p123);
+        self.flatten_cubic([p0, p01, p012, centre], points, depth + 1);
+        self.flatten_cubic([centre, p123, p23, p3], points, depth + 1);
+    }
+
+    /// How many straight walls a circular arc needs to stay within the
+    /// tolerance.
+    fn segments_for(&self, radius: f64, angle: f64) -> usize {
+        let step = if self.tolerance < radius {
+            2.0 * (1.0 - self.tolerance / radius).acos()
+        } else {
+            PI
+        };
+        ((angle / step).ceil() as usize).clamp(8, 1024)
+    }
+
+    /// Adds walls between the points after transforming them, leaving out
+    /// the ones with no length.
+    fn add_outline(&mut self, node: Node, matrix: Matrix, points: &[(f64, f64)], closed: bool) {
+        let mut points = points
+            .iter()
+            .map(|&p| matrix.apply(p))
+            .map(|(x, y)| (x as f32, y as f32))
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .map(Point::from)
+            .collect::<Vec<_>>();
+        if closed && points.len() > 2 {
+            points.push(points[0]);
+        }
+
+        let material = self.material_of(node);
+        for pair in points.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if (end.x - start.x).hypot(end.y - start.y) <= f32::EPSILON {
+                continue;
+            }
+            let wall = self.scene.add_line_no_graphics(start, end);
+            self.scene.set_wall_material(wall, material);
+        }
+    }
+
+    /// The material for the stroke colour of `node` or the nearest ancestor
+    /// that has one, made the first time a colour is seen.
+    fn material_of(&mut self, node: Node) -> MaterialId {
+        let stroke = node.ancestors().find_map(|node| {
+            let style = node.attribute("style").unwrap_or_default();
+            let from_style = style.split(';').find_map(|rule| {
+                let (name, value) = rule.split_once(':')?;
+                (name.trim() == "stroke").then(|| value.trim())
+            });
+            from_style.or_else(|| node.attribute("stroke"))
+        });
+        let colour = match stroke.and_then(|stroke| Color::from_str(stroke).ok()) {
+            Some(colour) => colour,
+            None => return MaterialId::DEFAULT,
+        };
+
+        let name = format!("#{:02x}{:02x}{:02x}", colour.red, colour.green, colour.blue);
+        self.scene.material_by_name(&name).unwrap_or_else(|| {
+            let colour = Colour::new(
+                colour.red as f32 / 255.0,
+                colour.green as f32 / 255.0,
+                colour.blue as f32 / 255.0,
+            );
+            self.scene.add_material(Material::new(name, colour))
+        })
+    }
+
+    /// A length attribute in user units, which is zero if it is missing.
+    fn number(&self, node: Node, attribute: &str) -> Result<f64, Error> {
+        let Some(value) = node.attribute(attribute) else {
+            return Ok(0.0);
+        };
+        let length = Length::from_str(value).map_err(|e| self.error(node, attribute, e))?;
+        let per = pixels_per(length.unit)
+            .ok_or_else(|| self.error(node, attribute, "relative lengths (%, em and ex) are not supported"))?;
+        let number = length.number * per;
+        if !(number as f32).is_finite() {
+            return Err(self.error(node, attribute, "too big"));
+        }
+        Ok(number)
+    }
+
+    /// The transform from the `viewBox` of the root `<svg>` to the size it
+    /// is shown at. Without a `width` and `height`, or with percentages,
+    /// the size is the size of the view box.
+    fn view_box(&self, root: Node) -> Result<Matrix, Error> {
+        let Some(value) = root.attribute("viewBox") else {
+            return Ok(Matrix::IDENTITY);
+        };
+        let view_box = ViewBox::from_str(value).map_err(|e| self.error(root, "viewBox", e))?;
+        if !(view_box.w > 0.0 && view_box.h > 0.0) {
+            return Err(self.error(root, "viewBox", "the width and height have to be above zero"));
+        }
+        let size = |attribute| match root.attribute(attribute).map(Length::from_str) {
+            Some(Ok(length)) if length.unit == LengthUnit::Percent => Ok(None),
+            Some(_) => self.number(root, attribute).map(Some),
+            None => Ok(None),
+        };
+        let width = size("width")?.unwrap_or(view_box.w);
+        let height = size("height")?.unwrap_or(view_box.h);
+        let aspect = match root.attribute("preserveAspectRatio") {
+            Some(value) => AspectRatio::from_str(value).map_err(|e| self.error(root, "preserveAspectRatio", e))?,
+            None => AspectRatio::default(),
+        };
+
+        let (sx, sy) = (width / view_box.w, height / view_box.h);
+        let (sx, sy, x, y) = match aspect.align {
+            Align::None => (sx, sy, 0.0, 0.0),
+            align => {
+                // Scaled the same both ways, and lined up in the space left
+                let scale = if aspect.slice { sx.max(sy) } else { sx.min(sy) };
+                let (x, y) = match align {
+                    Align::XMinYMin => (0.0, 0.0),
+                    Align::XMidYMin => (0.5, 0.0),
+                    Align::XMaxYMin => (1.0, 0.0),
+                    Align::XMinYMid => (0.0, 0.5),
+                    Align::XMinYMax => (0.0, 1.0),
+                    Align::XMidYMax => (0.5, 1.0),
+                    Align::XMaxYMid => (1.0, 0.5),
+                    Align::XMaxYMax => (1.0, 1.0),
+                    Align::XMidYMid | Align::None => (0.5, 0.5),
+                };
+                (scale, scale, (width - view_box.w * scale) * x, (height - view_box.h * scale) * y)
+            }
+        };
+        Ok(Matrix([sx, 0.0, 0.0, sy, x - view_box.x * sx, y - view_box.y * sy]))
+    }
+
+    fn error(&self, node: Node, attribute: &str, message: impl fmt::Display) -> Error {
+        Error::Attribute {
+            line: self.document.text_pos_at(node.range().start).row,
+            element: node.tag_name().name().to_string(),
+            attribute: attribute.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Reading walls out of orthogonal [Tiled](https://www.mapeditor.org) maps,
+//! in either the XML (`.tmx`) or JSON (`.tmj`) format.
+//!
+//! Rectangles, polygons, polylines and ellipses in object layers become
+//! walls around their outline. Every non-empty tile in a tile layer counts
+//! as solid, and only the outer boundary of each solid area becomes walls,
+//! with neighbouring edges merged so a straight run of tiles is one wall.
+//! Points, text and tile objects are skipped, as are hidden layers.
+
+use std::{
+    collections::HashSet,
+    f64::consts::PI,
+    fmt, fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use roxmltree::{Document, Node};
+use serde_json::Value;
+
+use crate::math::{Point, Scene};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    Json(serde_json::Error),
+    Map(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Xml(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "{}", e),
+            Error::Map(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(e: roxmltree::Error) -> Self {
+        Error::Xml(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TiledOptions {
+    /// The names of the layers to import. Every visible layer is imported
+    /// if this is empty.
+    pub layers: Vec<String>,
+}
+
+/// Loads a map, picking the format from the extension.
+pub fn load(path: impl AsRef<Path>, options: &TiledOptions) -> Result<Scene, Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tmx")) {
+        parse_tmx(&source, options)
+    } else {
+        parse_tmj(&source, options)
+    }
+}
+
+pub fn parse_tmx(source: &str, options: &TiledOptions) -> Result<Scene, Error> {
+    let document = Document::parse(source)?;
+    let map = document.root_element();
+    check_orientation(map.attribute("orientation"))?;
+
+    let mut walls = Walls {
+        tile_size: tile_size(number(map, "tilewidth")?, number(map, "tileheight")?)?,
+        options,
+        scene: Scene::new(),
+    };
+    walls.tmx_layers(map, (0.0, 0.0))?;
+    Ok(walls.scene)
+}
+
+pub fn parse_tmj(source: &str, options: &TiledOptions) -> Result<Scene, Error> {
+    let map: Value = serde_json::from_str(source)?;
+    check_orientation(map.get("orientation").and_then(Value::as_str))?;
+
+    let mut walls = Walls {
+        tile_size: tile_size(field(&map, "tilewidth")?, field(&map, "tileheight")?)?,
+        options,
+        scene: Scene::new(),
+    };
+    walls.tmj_layers(&map, (0.0, 0.0))?;
+    Ok(walls.scene)
+}
+
+/// Tiles with no width or height would give walls with no length.
+fn tile_size(width: f64, height: f64) -> Result<(f64, f64), Error> {
+    if width as f32 > 0.0 && height as f32 > 0.0 {
+        Ok((width, height))
+    } else {
+        Err(Error::Map(format!("tiles have to be bigger than 0, not {} by {}", width, height)))
+    }
+}
+
+fn check_orientation(orientation: Option<&str>) -> Result<(), Error> {
+    match orientation {
+        None | Some("orthogonal") => Ok(()),
+        Some(other) => Err(Error::Map(format!("{} maps are not supported, only orthogonal ones", other))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Rect,
+    Ellipse,
+    Polygon(Vec<(f64, f64)>),
+    Polyline(Vec<(f64, f64)>),
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Object {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Clockwise, in degrees
+    rotation: f64,
+    shape: Shape,
+}
+
+struct Walls<'a> {
+    tile_size: (f64, f64),
+    options: &'a TiledOptions,
+    scene: Scene,
+}
+
+impl Walls<'_> {
+    fn wanted(&self, name: &str, visible: bool) -> bool {
+        if self.options.layers.is_empty() {
+            visible
+        } else {
+            self.options.layers.iter().any(|layer| layer == name)
+        }
+    }
+
+    fn tmx_layers(&mut self, parent: Node, offset: (f64, f64)) -> Result<(), Error> {
+        for layer in parent.children().filter(Node::is_element) {
+            let visible = layer.attribute("visible") != Some("0");
+            let name = layer.attribute("name").unwrap_or_default();
+            let offset = (
+                offset.0 + number(layer, "offsetx")?,
+                offset.1 + number(layer, "offsety")?,
+            );
+
+            match layer.tag_name().name() {
+                "group" if visible => self.tmx_layers(layer, offset)?,
+                "layer" if self.wanted(name, visible) => {
+                    let mut tiles = HashSet::new();
+                    if let Some(data) = layer.children().find(|node| node.has_tag_name("data")) {
+                        let (width, height) = (number(layer, "width")? as i64, number(layer, "height")? as i64);
+                        tmx_tiles(data, (0, 0, width, height), &mut tiles)?;
+                        for chunk in data.children().filter(|node| node.has_tag_name("chunk")) {
+                            let area = (
+                                number(chunk, "x")? as i64,
+                                number(chunk, "y")? as i64,
+                                number(chunk, "width")? as i64,
+                                number(chunk, "height")? as i64,
+                            );
+                            tmx_tiles(chunk, area, &mut tiles)?;
+                        }
+                    }
+                    self.add_tiles(&tiles, offset)?;
+                }
+                "objectgroup" if self.wanted(name, visible) => {
+                    for object in layer.children().filter(|node| node.has_tag_name("object")) {
+                        if object.attribute("visible") == Some("0") {
+                            continue;
+                        }
+                        let child = |name| object.children().find(|node| node.has_tag_name(name));
+                        let points = |node: Node| {
+                            svgtypes::PointsParser::from(node.attribute("points").unwrap_or_default()).collect::<Vec<_>>()
+                        };
+                        let shape = if object.has_attribute("gid") || child("point").is_some() || child("text").is_some() {
+                            Shape::Skipped
+                        } else if child("ellipse").is_some() {
+                            Shape::Ellipse
+                        } else if let Some(polygon) = child("polygon") {
+                            Shape::Polygon(points(polygon))
+                        } else if let Some(polyline) = child("polyline") {
+                            Shape::Polyline(points(polyline))
+                        } else {
+                            Shape::Rect
+                        };
+                        self.add_object(
+                            &Object {
+                                x: number(object, "x")?,
+                                y: number(object, "y")?,
+                                width: number(object, "width")?,
+                                height: number(object, "height")?,
+                                rotation: number(object, "rotation")?,
+                                shape,
+                            },
+                            offset,
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn tmj_layers(&mut self, parent: &Value, offset: (f64, f64)) -> Result<(), Error> {
+        let layers = list(parent, "layers");
+        for layer in layers {
+            let visible = layer.get("visible").and_then(Value::as_bool).unwrap_or(true);
+            let name = layer.get("name").and_then(Value::as_str).unwrap_or_default();
+            let offset = (
+                offset.0 + optional_field(layer, "offsetx")?,
+                offset.1 + optional_field(layer, "offsety")?,
+            );
+
+            match layer.get("type").and_then(Value::as_str) {
+                Some("group") if visible => self.tmj_layers(layer, offset)?,
+                Some("tilelayer") if self.wanted(name, visible) => {
+                    let mut tiles = HashSet::new();
+                    if layer.get("data").is_some() {
+                        let area = (0, 0, field(layer, "width")? as i64, field(layer, "height")? as i64);
+                        tmj_tiles(layer, layer, area, &mut tiles)?;
+                    }
+                    for chunk in list(layer, "chunks") {
+                        let area = (
+                            field(chunk, "x")? as i64,
+                            field(chunk, "y")? as i64,
+                            field(chunk, "width")? as i64,
+                            field(chunk, "height")? as i64,
+                        );
+                        tmj_tiles(layer, chunk, area, &mut tiles)?;
+                    }
+                    self.add_tiles(&tiles, offset)?;
+                }
+                Some("objectgroup") if self.wanted(name, visible) => {
+                    for object in list(layer, "objects") {
+                        if object.get("visible").and_then(Value::as_bool) == Some(false) {
+                            continue;
+                        }
+                        let flag = |name| object.get(name).and_then(Value::as_bool).unwrap_or(false);
+                        let points = |value: &Value| -> Result<Vec<(f64, f64)>, Error> {
+                            let points = value
+                                .as_array()
+                                .ok_or_else(|| Error::Map("points have to be a list".to_string()))?;
+                            points.iter().map(|point| Ok((field(point, "x")?, field(point, "y")?))).collect()
+                        };
+                        let shape = if object.get("gid").is_some() || flag("point") || object.get("text").is_some() {
+                            Shape::Skipped
+                        } else if flag("ellipse") {
+                            Shape::Ellipse
+                        } else if let Some(polygon) = object.get("polygon") {
+                            Shape::Polygon(points(polygon)?)
+                        } else if let Some(polyline) = object.get("polyline") {
+                            Shape::Polyline(points(polyline)?)
+                        } else {
+                            Shape::Rect
+                        };
+                        self.add_object(
+                            &Object {
+                                x: optional_field(object, "x")?,
+                                y: optional_field(object, "y")?,
+                                width: optional_field(object, "width")?,
+                                height: optional_field(object, "height")?,
+                                rotation: optional_field(object, "rotation")?,
+                                shape,
+                            },
+                            offset,
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn add_object(&mut self, object: &Object, offset: (f64, f64)) -> Result<(), Error> {
+        let (width, height) = (object.width, object.height);
+        let (points, closed) = match &object.shape {
+            Shape::Rect => (vec![(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)], true),
+            Shape::Ellipse => {
+                let segments = 32;
+                let points = (0..segments)
+                    .map(|i| {
+                        let angle = 2.0 * PI * i as f64 / segments as f64;
+                        (width / 2.0 * (1.0 + angle.cos()), height / 2.0 * (1.0 + angle.sin()))
+                    })
+                    .collect();
+                (points, true)
+            }
+            Shape::Polygon(points) => (points.clone(), true),
+            Shape::Polyline(points) => (points.clone(), false),
+            Shape::Skipped => return Ok(()),
+        };
+
+        // Objects rotate around their own position
+        let (sin, cos) = object.rotation.to_radians().sin_cos();
+        let mut points = points
+            .into_iter()
+            .map(|(x, y)| {
+                let x2 = offset.0 + object.x + x * cos - y * sin;
+                let y2 = offset.1 + object.y + x * sin + y * cos;
+                point(x2, y2)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if closed && points.len() > 2 {
+            points.push(points[0]);
+        }
+        for pair in points.windows(2) {
+            if pair[0] != pair[1] {
+                self.scene.add_line_no_graphics(pair[0], pair[1]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds walls along every edge between a solid tile and an empty one,
+    /// merging edges that continue each other in a straight line.
+    fn add_tiles(&mut self, tiles: &HashSet<(i64, i64)>, offset: (f64, f64)) -> Result<(), Error> {
+        if tiles.is_empty() {
+            return Ok(());
+        }
+        let min_x = tiles.iter().map(|tile| tile.0).min().unwrap();
+        let max_x = tiles.iter().map(|tile| tile.0).max().unwrap();
+        let min_y = tiles.iter().map(|tile| tile.1).min().unwrap();
+        let max_y = tiles.iter().map(|tile| tile.1).max().unwrap();
+        let solid = |x, y| tiles.contains(&(x, y));
+        let (tile_width, tile_height) = self.tile_size;
+        let corner = |x: i64, y: i64| point(offset.0 + x as f64 * tile_width, offset.1 + y as f64 * tile_height);
+        // The other corners are all between these two
+        corner(min_x, min_y)?;
+        corner(max_x + 1, max_y + 1)?;
+        let corner = |x, y| corner(x, y).unwrap();
+
+        // A run of edges is only merged while the solid side stays the
+        // same, so that corners where two areas touch diagonally stay apart
+        for y in min_y..=max_y + 1 {
+            let mut run: Option<(i64, bool)> = None;
+            for x in min_x..=max_x + 1 {
+                let above = solid(x, y - 1);
+                let edge = (above != solid(x, y) && x <= max_x).then_some(above);
+                if let Some((start, side)) = run {
+                    if edge != Some(side) {
+                        self.add_edge(corner(start, y), corner(x, y));
+                        run = None;
+                    }
+                }
+                if run.is_none() {
+                    run = edge.map(|side| (x, side));
+                }
+            }
+        }
+        for x in min_x..=max_x + 1 {
+            let mut run: Option<(i64, bool)> = None;
+            for y in min_y..=max_y + 1 {
+                let left = solid(x - 1, y);
+                let edge = (left != solid(x, y) && y <= max_y).then_some(left);
+                if let Some((start, side)) = run {
+                    if edge != Some(side) {
+                        self.add_edge(corner(x, start), corner(x, y));
+                        run = None;
+                    }
+                }
+                if run.is_none() {
+                    run = edge.map(|side| (y, side));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a wall along a run of tile edges, unless it is so far out that
+    /// its ends round to the same point.
+    fn add_edge(&mut self, start: Point, end: Point) {
+        if start != end {
+            self.scene.add_line_no_graphics(start, end);
+        }
+    }
+}
+
+/// Bits of a tile id that say how the tile is flipped rather than which
+/// tile it is.
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+fn insert_tiles(gids: &[u32], (x, y, width, _): (i64, i64, i64, i64), tiles: &mut HashSet<(i64, i64)>) {
+    for (i, gid) in gids.iter().enumerate() {
+        if gid & !FLIP_FLAGS != 0 && width > 0 {
+            let i = i as i64;
+            tiles.insert((x + i % width, y + i / width));
+        }
+    }
+}
+
+fn tmx_tiles(data: Node, area: (i64, i64, i64, i64), tiles: &mut HashSet<(i64, i64)>) -> Result<(), Error> {
+    let text = data.text().unwrap_or_default();
+    let encoding = data.attribute("encoding").or_else(|| data.parent_element()?.attribute("encoding"));
+    let compression = data.attribute("compression").or_else(|| data.parent_element()?.attribute("compression"));
+
+    let gids = match encoding {
+        Some("csv") => text
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| Error::Map(format!("`{}` is not a tile id", gid))))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some("base64") => decode_gids(text, compression)?,
+        // Plain XML, with a <tile> element per tile
+        _ => data
+            .children()
+            .filter(|node| node.has_tag_name("tile"))
+            .map(|tile| Ok(number(tile, "gid")? as u32))
+            .collect::<Result<Vec<_>, Error>>()?,
+    };
+    insert_tiles(&gids, area, tiles);
+    Ok(())
+}
+
+fn tmj_tiles(layer: &Value, chunk: &Value, area: (i64, i64, i64, i64), tiles: &mut HashSet<(i64, i64)>) -> Result<(), Error> {
+    let gids = match chunk.get("data") {
+        Some(Value::Array(gids)) => gids
+            .iter()
+            .map(|gid| gid.as_f64().map(|gid| gid as u32).ok_or_else(|| Error::Map("tile ids have to be numbers".to_string())))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(Value::String(text)) => decode_gids(text, layer.get("compression").and_then(Value::as_str))?,
+        _ => return Err(Error::Map("tile data has to be a list or a string".to_string())),
+    };
+    insert_tiles(&gids, area, tiles);
+    Ok(())
+}
+
+/// Decodes base64 tile data, which is a list of little endian tile ids
+/// that might also be compressed.
+fn decode_gids(text: &str, compression: Option<&str>) -> Result<Vec<u32>, Error> {
+    let text = text.split_ascii_whitespace().collect::<String>();
+    let bytes = STANDARD
+        .decode(text)
+        .map_err(|_| Error::Map("tile data is not valid base64".to_string()))?;
+    let bytes = match compression {
+        None | Some("") => bytes,
+        Some("zlib") => {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+            out
+        }
+        Some("gzip") => {
+            let mut out = Vec::new();
+            flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+            out
+        }
+        Some(other) => return Err(Error::Map(format!("{} compressed tile data is not supported", other))),
+    };
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+/// A number attribute, which is zero if it is missing.
+fn number(node: Node, attribute: &str) -> Result<f64, Error> {
+    match node.attribute(attribute) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|value| finite(*value))
+            .ok_or_else(|| Error::Map(format!("`{}` of <{}> is not a number", attribute, node.tag_name().name()))),
+        None => Ok(0.0),
+    }
+}
+
+/// Whether `value` is a number that still is one as an `f32`, which rules
+/// out `NaN`, `inf` and anything too big for the scene.
+fn finite(value: f64) -> bool {
+    (value as f32).is_finite()
+}
+
+/// A point in the scene, which has to fit in an `f32` after the offsets and
+/// rotations are added up.
+fn point(x: f64, y: f64) -> Result<Point, Error> {
+    if finite(x) && finite(y) {
+        Ok((x as f32, y as f32).into())
+    } else {
+        Err(Error::Map(format!("{}, {} is too far out to be in the scene", x, y)))
+    }
+}
+
+/// The list called `name`, which is empty if it is missing.
+fn list<'a>(value: &'a Value, name: &str) -> &'a [Value] {
+    value.get(name).and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+fn field(value: &Value, name: &str) -> Result<f64, Error> {
+    value
+        .get(name)
+        .and_then(Value::as_f64)
+        .filter(|value| finite(*value))
+        .ok_or_else(|| Error::Map(format!("`{}` is missing or not a number", name)))
+}
+
+fn optional_field(value: &Value, name: &str) -> Result<f64, Error> {
+    match value.get(name) {
+        Some(_) => field(value, name),
+        None => Ok(0.0),
+    }
+}
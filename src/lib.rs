@@ -0,0 +1,25 @@
+pub mod acoustics;
+pub mod animation;
+pub mod arena;
+pub mod bounce;
+pub mod bvh;
+pub mod camera;
+pub mod colour;
+pub mod import;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod navigation;
+pub mod offset;
+pub mod optimize;
+pub mod random;
+pub mod render;
+pub mod scene_file;
+pub mod spectrum;
+pub mod stats;
+pub mod svg;
+pub mod timeline;
+pub mod visibility;
+pub mod world;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,163 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::{
+    colour::Colour,
+    math::{Bounds, Line, Point},
+};
+
+pub const RAY_COUNT: usize = 360;
+
+/// The radius of the light you get when a scene does not have any
+pub const DEFAULT_RADIUS: f32 = 500.0;
+
+/// How wide the soft edge of a spotlight is, in radians. Narrow cones fade
+/// out over half their width instead.
+pub const CONE_EDGE: f32 = 0.15;
+
+/// The narrowest a spotlight can be, in radians. Anything narrower is
+/// widened to this, since a cone with no width has no edge to fade across.
+pub const MIN_CONE: f32 = 0.01;
+
+/// A point light, which can be narrowed down to a spotlight shining
+/// `cone` radians wide around `direction`. The position, direction and
+/// cone are the only state that matters, the rays are always regenerated
+/// from them so that they can never drift away from where the light
+/// actually is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    position: Point,
+    radius: f32,
+    direction: f32,
+    cone: f32,
+    colour: Colour,
+    rays: Vec<Line>,
+}
+
+impl Light {
+    pub fn new(position: Point, radius: f32) -> Self {
+        Self::spot(position, radius, 0.0, TAU)
+    }
+
+    /// A light facing `direction`, in radians clockwise from the x axis,
+    /// that only shines `cone` radians wide. A cone of a whole turn or
+    /// more shines all around like [`Light::new`], and one narrower than
+    /// [`MIN_CONE`] is widened to it.
+    pub fn spot(position: Point, radius: f32, direction: f32, cone: f32) -> Self {
+        let mut light = Self {
+            position,
+            radius,
+            direction,
+            cone: cone.clamp(MIN_CONE, TAU),
+            colour: Colour::WHITE,
+            rays: Vec::new(),
+        };
+        light.regenerate_rays();
+        light
+    }
+
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius;
+        self.regenerate_rays();
+    }
+
+    pub fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    pub fn set_colour(&mut self, colour: Colour) {
+        self.colour = colour;
+    }
+
+    pub fn direction(&self) -> f32 {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: f32) {
+        self.direction = direction;
+        self.regenerate_rays();
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.set_direction((self.direction + angle).rem_euclid(TAU));
+    }
+
+    /// How wide the light shines, in radians.
+    pub fn cone(&self) -> f32 {
+        self.cone
+    }
+
+    pub fn set_cone(&mut self, cone: f32) {
+        self.cone = cone.clamp(MIN_CONE, TAU);
+        self.regenerate_rays();
+    }
+
+    /// Whether the light only shines in some directions.
+    pub fn is_spot(&self) -> bool {
+        self.cone < TAU
+    }
+
+    /// How wide the soft edge of the cone is, in radians.
+    pub fn cone_edge(&self) -> f32 {
+        CONE_EDGE.min(self.cone / 2.0)
+    }
+
+    /// How much of the light goes in the direction of `offset`, between 0
+    /// outside the cone and 1 inside it, fading across the edge. It only
+    /// depends on the angle, so `offset` can be in world or screen units.
+    pub fn cone_falloff(&self, offset: Point) -> f32 {
+        if !self.is_spot() {
+            return 1.0;
+        }
+        let angle = offset.y.atan2(offset.x);
+        let from_middle = ((angle - self.direction + PI).rem_euclid(TAU) - PI).abs();
+        ((self.cone / 2.0 - from_middle) / self.cone_edge()).clamp(0.0, 1.0)
+    }
+
+    /// The box everything this light can reach fits in.
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: (self.position.x - self.radius, self.position.y - self.radius).into(),
+            max: (self.position.x + self.radius, self.position.y + self.radius).into(),
+        }
+    }
+
+    pub fn rays(&self) -> &[Line] {
+        &self.rays
+    }
+
+    pub fn move_to(&mut self, position: Point) {
+        self.position = position;
+        self.regenerate_rays();
+    }
+
+    /// Spotlights get rays as close together as a full light's, from one
+    /// edge of the cone to the other.
+    fn regenerate_rays(&mut self) {
+        let angles = if self.is_spot() {
+            // At least one ray for each edge
+            let count = ((RAY_COUNT as f32 * self.cone / TAU).ceil() as usize + 1).max(2);
+            let start = self.direction - self.cone / 2.0;
+            (0..count)
+                .map(|i| start + self.cone * i as f32 / (count - 1) as f32)
+                .collect::<Vec<_>>()
+        } else {
+            (0..RAY_COUNT)
+                .map(|x| x as f32 * 2.0 * PI / RAY_COUNT as f32)
+                .collect()
+        };
+        let (position, radius) = (self.position, self.radius);
+        self.rays = angles
+            .into_iter()
+            .map(|x| (x.cos() * radius, x.sin() * radius).into())
+            .map(|offset| Line::from_points(position, position + offset))
+            .collect();
+    }
+}
@@ -0,0 +1,192 @@
+//! The fragment shader the viewer draws lights with. It is compiled once
+//! rather than every frame, and when it comes from a file given with
+//! `--shader`, compiled again whenever that file changes.
+//!
+//! Shaders get these uniforms, all in window pixels and radians:
+//!
+//! - `vec2 Centre`: where the light is
+//! - `vec3 Colour`: the colour of the light, with every channel from 0 to
+//!   1, after going through any glass. Each colour is drawn separately
+//! - `float Radius`: how far the light reaches, before `RADIUS_MOD`
+//! - `float Direction`, `float HalfCone`, `float ConeEdge`: the way a
+//!   spotlight faces, how far either side of that it shines and how wide
+//!   its soft edge is. Lights that shine all around have a `HalfCone`
+//!   bigger than pi
+//! - `float Time`: seconds since the viewer started
+//!
+//! A shader does not have to use all of them.
+//!
+//! Where shaders are not available or the built in one does not compile,
+//! like on some software renderers, there is no shader and the viewer
+//! fades the light out with vertex colours instead, using the brightness
+//! from [`tracy::render::light_triangles`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use sfml::graphics::Shader;
+
+pub const BUILT_IN: &str = include_str!("./frag.frag");
+
+/// How often the shader file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct LightShader {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    shader: Option<Shader<'static>>,
+    error: Option<String>,
+}
+
+impl LightShader {
+    pub fn built_in() -> Self {
+        let mut light_shader = Self::none();
+        light_shader.use_built_in();
+        light_shader
+    }
+
+    /// No shader at all, so lights are always faded out on the CPU.
+    pub fn none() -> Self {
+        Self {
+            path: None,
+            modified: None,
+            last_poll: Instant::now(),
+            shader: None,
+            error: None,
+        }
+    }
+
+    /// Loads the shader at `path`. If that does not work the built in one
+    /// is used until the file is fixed, if it works itself, and
+    /// [`LightShader::error`] says what went wrong.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if !Shader::is_available() {
+            let mut light_shader = Self::none();
+            light_shader.error = Some(format!("shaders are not available, so {} is not used", path.display()));
+            return light_shader;
+        }
+        let mut light_shader = Self {
+            modified: modified(&path),
+            path: Some(path.clone()),
+            last_poll: Instant::now(),
+            shader: None,
+            error: None,
+        };
+        match compile_file(&path) {
+            Ok(shader) => light_shader.shader = Some(shader),
+            Err(e) => {
+                light_shader.use_built_in();
+                light_shader.error = Some(e);
+            }
+        }
+        light_shader
+    }
+
+    /// Leaves no shader if the built in one does not compile either, which
+    /// the F3 overlay shows as "cpu falloff".
+    fn use_built_in(&mut self) {
+        if !Shader::is_available() {
+            return;
+        }
+        self.shader = Shader::from_memory(None, None, Some(BUILT_IN));
+    }
+
+    /// Compiles the shader again if its file has changed since the last
+    /// time, and says whether it did. A shader that does not compile leaves
+    /// the old one in use.
+    pub fn poll(&mut self) -> bool {
+        let Some(path) = &self.path else { return false };
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified(path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+
+        match compile_file(path) {
+            Ok(shader) => {
+                self.shader = Some(shader);
+                self.error = None;
+                true
+            }
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    /// The shader to draw lights with, if there is one that works.
+    pub fn shader_mut(&mut self) -> Option<&mut Shader<'static>> {
+        self.shader.as_mut()
+    }
+
+    /// Why the last attempt at compiling failed, if it did, with the
+    /// compiler's log where it could be caught.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn compile_file(path: &Path) -> Result<Shader<'static>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let (shader, log) = capture_stderr(|| Shader::from_memory(None, None, Some(&source)));
+    shader.ok_or_else(|| match log.trim() {
+        "" => format!("could not compile {}, see the log in the terminal", path.display()),
+        log => format!("could not compile {}:\n{}", path.display(), log),
+    })
+}
+
+/// Runs `f` with stderr going to a temporary file and gives back what was
+/// written to it. SFML prints the compiler's log to stderr, and the
+/// bindings have no way of setting where its errors go, so the file
+/// descriptor itself is swapped out. The log is empty if that fails.
+#[cfg(unix)]
+fn capture_stderr<T>(f: impl FnOnce() -> T) -> (T, String) {
+    use std::{
+        io::{self, Read, Seek, SeekFrom, Write},
+        os::fd::AsRawFd,
+    };
+
+    let Ok(mut file) = tempfile::tempfile() else {
+        return (f(), String::new());
+    };
+    let _ = io::stderr().flush();
+    // SAFETY: these only duplicate and close file descriptors, and stderr
+    // is always put back before returning
+    let saved = unsafe { libc::dup(libc::STDERR_FILENO) };
+    if saved < 0 {
+        return (f(), String::new());
+    }
+    if unsafe { libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+        unsafe { libc::close(saved) };
+        return (f(), String::new());
+    }
+    let result = f();
+    unsafe {
+        libc::dup2(saved, libc::STDERR_FILENO);
+        libc::close(saved);
+    }
+
+    let mut log = String::new();
+    let _ = file.seek(SeekFrom::Start(0)).and_then(|_| file.read_to_string(&mut log));
+    (result, log)
+}
+
+#[cfg(not(unix))]
+fn capture_stderr<T>(f: impl FnOnce() -> T) -> (T, String) {
+    (f(), String::new())
+}
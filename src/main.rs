@@ -1,21 +1,105 @@
-mod math;
-#[cfg(test)]
-mod tests;
+mod bounce_view;
+mod cli;
+mod light_shader;
+mod overlay;
 
-use std::f32::consts::PI;
+use std::{env, f32::consts::TAU, process, time::{Duration, Instant}};
 
-use math::{Line, Point, Scene};
 use sfml::{
     graphics::{self, RenderTarget, RenderWindow},
-    window::{self, mouse, Style}, system,
+    system,
+    window::{self, mouse, Style},
+};
+use bounce_view::BounceView;
+use light_shader::LightShader;
+use overlay::Corner;
+use tracy::{
+    bounce::BounceSettings,
+    camera::Camera,
+    light::{Light, DEFAULT_RADIUS},
+    colour::Colour,
+    math::{Point, Scene},
+    render::{self, RADIUS_MOD},
+    scene_file::SceneFile,
+    spectrum,
+    stats::Stats,
+    timeline::{Playback, Timeline},
 };
 
 const WINDOW_WIDTH: u32 = 1200;
 const WINDOW_HEIGHT: u32 = 1200;
 
-const FRAG_SHADER: &str = include_str!("./frag.frag");
+/// How much one notch of the mouse wheel zooms in or out
+const ZOOM_STEP: f32 = 1.1;
+
+/// How far the arrow keys move through the timeline, in seconds
+const SCRUB_STEP: f32 = 0.1;
+
+/// How far Q and E, or one notch of the mouse wheel with control held,
+/// turn the light, in degrees
+const TURN_STEP: f32 = 5.0;
+
+/// How wide the light is when F turns it into a flashlight, and how much
+/// the brackets widen or narrow it, in degrees
+const FLASHLIGHT_CONE: f32 = 60.0;
+const CONE_STEP: f32 = 5.0;
+
+/// How long a notice stays in the corner of the window
+const NOTICE_TIME: Duration = Duration::from_secs(3);
+
+fn default_scene() -> Scene {
+    let mut scene = Scene::new();
+
+    // TODO: Support vertical lines
+    scene.add_line_no_graphics(
+        ((WINDOW_WIDTH / 2) as f32, 0.0).into(),
+        ((WINDOW_WIDTH / 2) as f32 + 0.1, (WINDOW_HEIGHT / 2) as f32).into(),
+    );
+    scene
+}
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match args.first().map(String::as_str) {
+        Some("render") => cli::render(&args[1..]),
+        Some("export-svg") => cli::export_svg(&args[1..]),
+        Some("acoustics") => cli::acoustics(&args[1..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        _ => view(&args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn view(args: &[String]) -> Result<(), String> {
+    let flags = cli::Flags::parse(args, &["shader", "bounces", "samples", "spp"], &["no-shader"])?;
+    if flags.switch("no-shader") && flags.get("shader").is_some() {
+        return Err("--shader and --no-shader can not be used together".to_string());
+    }
+    let SceneFile {
+        mut scene,
+        mut lights,
+        mut timeline,
+        ..
+    } = match flags.positional(0) {
+        Some(_) => cli::load_scene(&flags)?,
+        None => SceneFile {
+            scene: default_scene(),
+            ..Default::default()
+        },
+    };
+
+    if lights.is_empty() {
+        lights.push(Light::new((0.0, 0.0).into(), DEFAULT_RADIUS));
+    }
+
     let mut window = RenderWindow::new(
         (WINDOW_HEIGHT, WINDOW_WIDTH),
         "Tracy!",
@@ -24,30 +108,43 @@ fn main() {
     );
 
     let mut scene_vertices = graphics::VertexArray::new(graphics::PrimitiveType::LINES, 2);
+    scene.re_init_graphics(&mut scene_vertices);
 
-    let mut scene = Scene::new();
+    let mut moving = true;
 
-    // TODO: Support vertical lines
-    scene.add_line(
-        ((WINDOW_WIDTH / 2) as f32, 0.0).into(),
-        ((WINDOW_WIDTH / 2) as f32 + 0.1, (WINDOW_HEIGHT / 2) as f32).into(),
-        &mut scene_vertices,
-    );
+    let mut playback = Playback::new(timeline.duration());
+    let mut clock = system::Clock::start();
 
-    const LINE_LENGTH: f32 = 500.0;
+    let mut camera = Camera::new(WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut mouse_pos: Point = (0.0, 0.0).into();
+    let mut panning = false;
 
-    let mut rays = (0..(360 * 4))
-        .into_iter()
-        .map(|x| (x / 4) as f32 * PI / 180.0)
-        .map(|x| Line::new(0.0, 0.0, x.cos() * LINE_LENGTH, x.sin() * LINE_LENGTH))
-        .collect::<Vec<_>>();
+    let mut stats = Stats::new();
+    let mut show_stats = false;
 
-    let mut prev_x = 0;
-    let mut prev_y = 0;
+    let mut light_shader = match flags.get("shader") {
+        _ if flags.switch("no-shader") => LightShader::none(),
+        Some(path) => LightShader::from_path(path),
+        None => LightShader::built_in(),
+    };
+    let started = Instant::now();
+    // Something that happened, shown for a moment
+    let mut notice: Option<(String, Instant)> = None;
 
-    let mut moving = true;
+    // Bounces are drawn from the start if they were asked for, and G turns
+    // them on and off either way
+    let settings = cli::bounce_settings(&flags, bounce_view::PATHS_PER_FRAME)?;
+    let default = BounceSettings {
+        samples: bounce_view::PATHS_PER_FRAME,
+        ..Default::default()
+    };
+    let spp = cli::spp(&flags, bounce_view::PASSES)?;
+    let mut bounce_view = BounceView::new(settings.unwrap_or(default), spp, settings.is_some());
 
     while window.is_open() {
+        let frame_start = Instant::now();
+        stats.reset();
+
         while let Some(event) = window.poll_event() {
             match event {
                 window::Event::Closed => window.close(),
@@ -55,74 +152,247 @@ fn main() {
                     code: c,
                     alt: _,
                     ctrl: _,
-                    shift: _,
+                    shift,
                     system: _,
                 } => match c {
                     window::Key::C => {
                         scene_vertices.clear();
-                        scene = Scene::new();
-                        scene.add_line(
-                            ((WINDOW_WIDTH / 2) as f32, 0.0).into(),
-                            ((WINDOW_WIDTH / 2) as f32 + 0.1, (WINDOW_HEIGHT / 2) as f32).into(),
-                            &mut scene_vertices,
-                        );
+                        scene = default_scene();
+                        scene.re_init_graphics(&mut scene_vertices);
+                        timeline = Timeline::new();
+                        playback = Playback::new(0.0);
                     }
                     window::Key::ESCAPE => window.close(),
                     window::Key::S => {
                         moving = !moving;
                     }
+                    window::Key::O => {
+                        let report = scene.optimize(&timeline.animated_walls());
+                        notice = Some((report.to_string(), Instant::now()));
+                        scene_vertices.clear();
+                        scene.re_init_graphics(&mut scene_vertices);
+                    }
+                    window::Key::F3 => show_stats = !show_stats,
+                    window::Key::G => bounce_view.enabled = !bounce_view.enabled,
+                    window::Key::SPACE => playback.toggle(),
+                    window::Key::HOME => playback.seek(0.0),
+                    window::Key::LEFT | window::Key::RIGHT => {
+                        let step = if shift { SCRUB_STEP * 10.0 } else { SCRUB_STEP };
+                        let step = if c == window::Key::LEFT { -step } else { step };
+                        playback.scrub(step);
+                    }
+                    window::Key::Q | window::Key::E => {
+                        let step = if shift { TURN_STEP * 3.0 } else { TURN_STEP };
+                        let step = if c == window::Key::Q { -step } else { step };
+                        lights[0].rotate(step.to_radians());
+                    }
+                    window::Key::F => {
+                        let cone = if lights[0].is_spot() { TAU } else { FLASHLIGHT_CONE.to_radians() };
+                        lights[0].set_cone(cone);
+                    }
+                    window::Key::LBRACKET | window::Key::RBRACKET if lights[0].is_spot() => {
+                        let step = if c == window::Key::LBRACKET { -CONE_STEP } else { CONE_STEP };
+                        // Never quite the whole circle, or it would stop being a spotlight
+                        let cone = (lights[0].cone() + step.to_radians()).clamp(CONE_STEP.to_radians(), TAU - 0.01);
+                        lights[0].set_cone(cone);
+                    }
                     _ => {}
                 },
+                window::Event::Resized { width, height } => camera.resize(width, height),
+                window::Event::MouseWheelScrolled {
+                    wheel: mouse::Wheel::Vertical,
+                    delta,
+                    ..
+                } if window::Key::LCONTROL.is_pressed() || window::Key::RCONTROL.is_pressed() => {
+                    lights[0].rotate((TURN_STEP * delta).to_radians());
+                }
+                window::Event::MouseWheelScrolled {
+                    wheel: mouse::Wheel::Vertical,
+                    delta,
+                    x,
+                    y,
+                } => {
+                    camera.zoom_at((x as f32, y as f32).into(), ZOOM_STEP.powf(delta));
+                    if moving && !timeline.moves_light(0) {
+                        lights[0].move_to(camera.screen_to_world(mouse_pos));
+                    }
+                }
+                window::Event::MouseButtonPressed { button: mouse::Button::MIDDLE, .. } => {
+                    panning = true;
+                }
+                window::Event::MouseButtonReleased { button: mouse::Button::MIDDLE, .. } => {
+                    panning = false;
+                }
                 window::Event::MouseButtonPressed { button: _, x, y } => {
                     let x = x as f32;
                     let y = y as f32;
-                    let click_point: Point = (x, y).into();
+                    let click_point = camera.screen_to_world((x, y).into());
 
                     scene.add_line_continuous(click_point, &mut scene_vertices);
                 }
                 window::Event::MouseMoved { x, y } => {
-                    if moving {
-                        let dx = x - prev_x;
-                        let dy = y - prev_y;
+                    let new_pos: Point = (x as f32, y as f32).into();
+                    if panning {
+                        camera.pan(new_pos.x - mouse_pos.x, new_pos.y - mouse_pos.y);
+                    }
+                    mouse_pos = new_pos;
 
-                        for ray in &mut rays {
-                            ray.offset(dx as f32, dy as f32);
-                        }
-                        prev_x = x;
-                        prev_y = y;
+                    if moving && !timeline.moves_light(0) {
+                        lights[0].move_to(camera.screen_to_world(mouse_pos));
                     }
                 }
 
                 _ => {}
             }
         }
-        window.set_active(true);
-        window.clear(graphics::Color::BLACK);
+        stats.record("events", frame_start.elapsed());
 
-        let mut rays_arr = graphics::VertexArray::new(graphics::PrimitiveType::TRIANGLE_STRIP, 0);
-        for ray in rays.iter() {
-            let collided_ray = ray.cast_in_scene(&scene);
+        if light_shader.poll() {
+            notice = Some((format!("reloaded {}", flags.get("shader").unwrap_or_default()), Instant::now()));
+        }
 
-            let (start_v, end_v) = collided_ray.renderable(graphics::Color::WHITE);
-            rays_arr.append(&start_v);
-            rays_arr.append(&end_v);
+        let dt = clock.restart().as_seconds();
+        if !timeline.is_empty() {
+            let start = Instant::now();
+            playback.advance(dt);
+            timeline.evaluate(playback.time, &mut scene, &mut lights);
+
+            scene_vertices.clear();
+            scene.re_init_graphics(&mut scene_vertices);
+            stats.record("timeline", start.elapsed());
         }
 
+        window.set_active(true);
+        window.clear(graphics::Color::BLACK);
+        window.set_view(&camera.view());
+
         window.draw(&scene_vertices);
 
-        if let Some(mut shader) = graphics::Shader::from_memory(None, None, Some(FRAG_SHADER)) {
-            shader.set_uniform_vec2("Centre", (prev_x as f32, prev_y as f32).into());
-            shader.set_uniform_vec3("Colour", (255.0, 255.0, 255.0).into());
-            shader.set_uniform_float("Radius", LINE_LENGTH);
+        for light in &lights {
+            let start = Instant::now();
+            // Without a shader the falloff is faded across each triangle by
+            // the vertex colours, which only works out if the rays stop
+            // where the light runs out
+            let reach = match light_shader.shader_mut() {
+                Some(_) => f32::INFINITY,
+                None => light.radius() * RADIUS_MOD,
+            };
+            let triangles = render::light_triangles(&scene, light, reach, &mut stats);
+            stats.record("cast", start.elapsed());
+
+            let start = Instant::now();
+            if let Some(shader) = light_shader.shader_mut() {
+                // The shader works in window pixels, not world units
+                shader.set_uniform_vec2("Centre", camera.world_to_screen(light.position()).into());
+                shader.set_uniform_float("Radius", light.radius() * camera.zoom());
+                shader.set_uniform_float("Direction", light.direction());
+                if light.is_spot() {
+                    shader.set_uniform_float("HalfCone", light.cone() / 2.0);
+                    shader.set_uniform_float("ConeEdge", light.cone_edge());
+                } else {
+                    shader.set_uniform_float("HalfCone", TAU);
+                    shader.set_uniform_float("ConeEdge", 1.0);
+                }
+                shader.set_uniform_float("Time", started.elapsed().as_secs_f32());
 
+                // One draw for every colour the light is tinted to by glass
+                let mut batches: Vec<(Colour, graphics::VertexArray)> = Vec::new();
+                for triangle in &triangles {
+                    let index = match batches.iter().position(|(colour, _)| *colour == triangle.colour) {
+                        Some(index) => index,
+                        None => {
+                            let vertices = graphics::VertexArray::new(graphics::PrimitiveType::TRIANGLES, 0);
+                            batches.push((triangle.colour, vertices));
+                            batches.len() - 1
+                        }
+                    };
+                    for point in triangle.points {
+                        batches[index].1.append(&graphics::Vertex::with_pos(point.into()));
+                    }
+                }
+                for (colour, vertices) in &batches {
+                    shader.set_uniform_vec3("Colour", (colour.r, colour.g, colour.b).into());
+                    let mut states = graphics::RenderStates::default();
+                    states.set_shader(Some(shader));
+                    window.draw_with_renderstates(vertices, &states);
+                }
+            } else {
+                let mut vertices = graphics::VertexArray::new(graphics::PrimitiveType::TRIANGLES, 0);
+                for triangle in &triangles {
+                    let [r, g, b] = triangle.colour.to_rgb8();
+                    for (point, brightness) in triangle.points.into_iter().zip(triangle.brightness) {
+                        let colour = graphics::Color::rgba(r, g, b, (brightness * 255.0) as u8);
+                        vertices.append(&graphics::Vertex::with_pos_color(point.into(), colour));
+                    }
+                }
+                window.draw(&vertices);
+            }
+            stats.record("draw", start.elapsed());
+        }
+
+        // Rainbows from prisms are added on top with vertex colours, with
+        // or without a shader
+        if spectrum::disperses(&scene) {
+            let start = Instant::now();
+            let mut dispersed = graphics::VertexArray::new(graphics::PrimitiveType::TRIANGLES, 0);
+            for light in &lights {
+                for triangle in render::dispersed_triangles(&scene, light, &mut stats) {
+                    for (point, brightness) in triangle.points.into_iter().zip(triangle.brightness) {
+                        let colour = graphics::Color::from(triangle.colour * brightness);
+                        dispersed.append(&graphics::Vertex::with_pos_color(point.into(), colour));
+                    }
+                }
+            }
             let mut states = graphics::RenderStates::default();
-            states.set_shader(Some(&shader));
+            states.set_blend_mode(graphics::BlendMode::ADD);
+            window.draw_with_renderstates(&dispersed, &states);
+            stats.record("disperse", start.elapsed());
+        }
 
-            window.draw_with_renderstates(&rays_arr, &states)
-        } else {
-            panic!("Could not use shader!");
+        let start = Instant::now();
+        let size = window.size();
+        bounce_view.update(&scene, &lights, &camera, (size.x, size.y), &mut stats);
+        bounce_view.draw(&mut window);
+        if bounce_view.enabled {
+            stats.record("bounce", start.elapsed());
+        }
+
+        if show_stats {
+            let bounces = if bounce_view.enabled {
+                let noise = match bounce_view.noise() {
+                    Some(noise) => format!("{:.4}", noise),
+                    None => "-".to_string(),
+                };
+                format!(
+                    "bounces: {} ({}/{} passes, noise {})\n",
+                    bounce_view.settings.bounces,
+                    bounce_view.passes(),
+                    bounce_view.spp,
+                    noise
+                )
+            } else {
+                String::new()
+            };
+            let text = format!(
+                "{:.0} fps ({:.2} ms)\nwalls: {}\nlights: {} ({})\n{}{}",
+                1.0 / dt.max(f32::EPSILON),
+                dt * 1000.0,
+                scene.wall_count(),
+                lights.len(),
+                if light_shader.shader_mut().is_some() { "shader" } else { "cpu falloff" },
+                bounces,
+                stats
+            );
+            overlay::draw_text(&mut window, &text, graphics::Color::WHITE, Corner::TopLeft);
+        }
+        if let Some(error) = light_shader.error() {
+            overlay::draw_text(&mut window, error, graphics::Color::RED, Corner::BottomLeft);
+        } else if let Some((text, _)) = notice.as_ref().filter(|(_, at)| at.elapsed() < NOTICE_TIME) {
+            overlay::draw_text(&mut window, text, graphics::Color::WHITE, Corner::BottomLeft);
         }
 
         window.display();
     }
+
+    Ok(())
 }
@@ -0,0 +1,66 @@
+use crate::{colour::Colour, spectrum::Cauchy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub(crate) usize);
+
+impl MaterialId {
+    /// The material every wall gets unless told otherwise
+    pub const DEFAULT: MaterialId = MaterialId(0);
+}
+
+/// What a wall is made of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// The colour the wall is drawn in
+    pub colour: Colour,
+    /// What light going through the wall is multiplied by, or `None` if
+    /// the wall stops light
+    pub tint: Option<Colour>,
+    /// How much the wall bends light going into it, or `None` if it does
+    /// not. Light that bends is split up by wavelength, so it is only
+    /// drawn by [`crate::spectrum`] and stopped everywhere else
+    pub refraction: Option<Cauchy>,
+    /// How much of the light hitting the wall bounces off it, for each
+    /// colour, when [`crate::bounce`] is used
+    pub albedo: Colour,
+    /// How much of the sound hitting the wall it soaks up instead of
+    /// reflecting, from 0 to 1, when [`crate::acoustics`] is used
+    pub absorption: f32,
+}
+
+impl Material {
+    pub fn new(name: impl Into<String>, colour: Colour) -> Self {
+        Self {
+            name: name.into(),
+            colour,
+            tint: None,
+            refraction: None,
+            albedo: Colour::new(0.5, 0.5, 0.5),
+            absorption: 0.1,
+        }
+    }
+
+    /// A material like stained glass, that lets light through tinted.
+    pub fn glass(name: impl Into<String>, colour: Colour, tint: Colour) -> Self {
+        Self {
+            tint: Some(tint),
+            ..Self::new(name, colour)
+        }
+    }
+
+    /// A material like a prism, that bends light and splits white light
+    /// into a rainbow.
+    pub fn refractive(name: impl Into<String>, colour: Colour, refraction: Cauchy) -> Self {
+        Self {
+            refraction: Some(refraction),
+            ..Self::new(name, colour)
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new("wall", Colour::GREEN)
+    }
+}
@@ -1,7 +1,18 @@
-use std::ops;
+use std::{
+    cell::{Cell, RefCell},
+    ops,
+};
 
 use sfml::{graphics, system};
 
+use crate::{
+    arena::{Arena, Index},
+    bvh::Bvh,
+    colour::Colour,
+    material::{Material, MaterialId},
+    stats::Stats,
+};
+
 macro_rules! line_impl {
     (mut $name:ident($($x:ident: $typ:ty),*) -> $return:ty) => {
         pub fn $name(&mut self, $($x: $typ,)*) -> $return {
@@ -64,59 +75,342 @@ macro_rules! line_inner {
     };
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WallId(Index);
+
+impl WallId {
+    /// The number of the wall, counting from 0 in the order walls were
+    /// added, which is the number a scene file uses for it. A wall added
+    /// after one is removed takes its number.
+    pub fn number(self) -> usize {
+        self.0.slot()
+    }
+}
+
+/// How a wall has been moved away from where it was added. The rotation
+/// (in radians) is around the middle of the wall and is applied before the
+/// translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Point,
+    pub rotation: f32,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: (0.0, 0.0).into(),
+            rotation: 0.0,
+        }
+    }
+
+    pub fn apply(&self, pivot: Point, point: Point) -> Point {
+        let (sin, cos) = self.rotation.sin_cos();
+        let rel = point - pivot;
+        let rotated = (rel.x * cos - rel.y * sin, rel.x * sin + rel.y * cos).into();
+        pivot + rotated + self.translation
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// A wall remembers where it was added, so that moving it every frame is
+/// always done from the same starting point and can not drift.
+#[derive(Debug, Clone)]
+struct Wall {
+    base: Line,
+    transform: Transform,
+    line: Line,
+    material: MaterialId,
+    proxy: usize,
+}
+
 #[derive(Debug)]
 pub struct Scene {
-    walls: Vec<Line>,
+    walls: Arena<Wall>,
+    materials: Vec<Material>,
+    tree: Bvh<Index>,
     last_point: Option<Point>
 }
 
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scene {
     pub fn new() -> Self {
         Self {
-            walls: Vec::new(),
+            walls: Arena::new(),
+            materials: vec![Material::default()],
+            tree: Bvh::new(),
             last_point: None
         }
     }
 
     #[allow(dead_code)]
     pub fn re_init_graphics(&self, arr: &mut graphics::VertexArray) {
-        for line in &self.walls {
-            let (start_v, end_v) = line.renderable(graphics::Color::GREEN);
+        for wall in self.walls.iter().map(|(_, wall)| wall) {
+            let colour = self.material(wall.material).colour.into();
+            let (start_v, end_v) = wall.line.renderable(colour);
 
             arr.append(&start_v);
             arr.append(&end_v);
         }
     }
 
-    pub fn add_line(&mut self, start: Point, end: Point, arr: &mut graphics::VertexArray) {
-        self.last_point = Some(end);
-        let line = Line::from_points(start, end);
-        let (start_v, end_v) = line.renderable(graphics::Color::GREEN);
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn material(&self, id: MaterialId) -> &Material {
+        &self.materials[id.0]
+    }
+
+    pub fn material_mut(&mut self, id: MaterialId) -> &mut Material {
+        &mut self.materials[id.0]
+    }
+
+    pub fn material_by_name(&self, name: &str) -> Option<MaterialId> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(MaterialId)
+    }
+
+    pub fn materials(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(i, material)| (MaterialId(i), material))
+    }
+
+    pub fn wall_material(&self, id: WallId) -> Option<MaterialId> {
+        self.walls.get(id.0).map(|wall| wall.material)
+    }
+
+    pub fn set_wall_material(&mut self, id: WallId, material: MaterialId) -> bool {
+        match self.walls.get_mut(id.0) {
+            Some(wall) => {
+                wall.material = material;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = &Line> {
+        self.walls.iter().map(|(_, wall)| &wall.line)
+    }
+
+    pub fn wall_ids(&self) -> impl Iterator<Item = (WallId, &Line)> {
+        self.walls.iter().map(|(index, wall)| (WallId(index), &wall.line))
+    }
+
+    /// The walls that could be inside `bounds`, along with a few just
+    /// outside it, which is a lot quicker than going through all of them.
+    pub fn walls_near(&self, bounds: &Bounds) -> Vec<(WallId, &Line)> {
+        let mut near = Vec::new();
+        self.tree.visit(
+            |node| node.overlaps(bounds),
+            |index| near.push((WallId(index), &self.walls.get(index).unwrap().line)),
+        );
+        near
+    }
+
+    pub fn wall(&self, id: WallId) -> Option<&Line> {
+        self.walls.get(id.0).map(|wall| &wall.line)
+    }
+
+    pub fn wall_count(&self) -> usize {
+        self.walls.len()
+    }
+
+    pub fn add_line(&mut self, start: Point, end: Point, arr: &mut graphics::VertexArray) -> WallId {
+        let id = self.add_line_no_graphics(start, end);
+        let colour = self.material(MaterialId::DEFAULT).colour.into();
+        let (start_v, end_v) = self.walls.get(id.0).unwrap().line.renderable(colour);
 
         arr.append(&start_v);
         arr.append(&end_v);
-        self.walls.push(line);
+        id
     }
 
-    pub fn add_line_continuous(&mut self, new: Point, arr: &mut graphics::VertexArray) {
-        if let Some(last) = self.last_point {
-            self.add_line(last, new, arr);
-        }
+    pub fn add_line_continuous(&mut self, new: Point, arr: &mut graphics::VertexArray) -> Option<WallId> {
+        self.last_point.map(|last| self.add_line(last, new, arr))
     }
 
-    #[allow(dead_code)]
-    pub fn add_line_no_graphics(&mut self, start: Point, end: Point) {
+    pub fn add_line_no_graphics(&mut self, start: Point, end: Point) -> WallId {
         self.last_point = Some(end);
         let line = Line::from_points(start, end);
+        let bounds = line.bounds();
 
-        self.walls.push(line);
+        let index = self.walls.insert(Wall {
+            base: line.clone(),
+            transform: Transform::identity(),
+            line,
+            material: MaterialId::DEFAULT,
+            proxy: 0,
+        });
+        let proxy = self.tree.insert(bounds, index);
+        self.walls.get_mut(index).unwrap().proxy = proxy;
+        WallId(index)
     }
 
     #[allow(dead_code)]
-    pub fn add_line_continuous_no_graphics(&mut self, new: Point) {
-        if let Some(last) = self.last_point {
-            self.add_line_no_graphics(last, new);
+    pub fn add_line_continuous_no_graphics(&mut self, new: Point) -> Option<WallId> {
+        self.last_point.map(|last| self.add_line_no_graphics(last, new))
+    }
+
+    pub fn remove_wall(&mut self, id: WallId) -> Option<Line> {
+        let wall = self.walls.remove(id.0)?;
+        self.tree.remove(wall.proxy);
+        Some(wall.line)
+    }
+
+    /// Gives a wall a new shape, forgetting about any transform it had.
+    pub fn set_wall(&mut self, id: WallId, start: Point, end: Point) -> bool {
+        match self.walls.get_mut(id.0) {
+            Some(wall) => {
+                wall.base = Line::from_points(start, end);
+                wall.transform = Transform::identity();
+            }
+            None => return false,
+        }
+        self.refit_wall(id);
+        true
+    }
+
+    pub fn wall_transform(&self, id: WallId) -> Option<Transform> {
+        self.walls.get(id.0).map(|wall| wall.transform)
+    }
+
+    pub fn set_wall_transform(&mut self, id: WallId, transform: Transform) -> bool {
+        match self.walls.get_mut(id.0) {
+            Some(wall) => wall.transform = transform,
+            None => return false,
+        }
+        self.refit_wall(id);
+        true
+    }
+
+    pub fn translate_wall(&mut self, id: WallId, x: f32, y: f32) -> bool {
+        match self.wall_transform(id) {
+            Some(mut transform) => {
+                transform.translation = transform.translation + (x, y).into();
+                self.set_wall_transform(id, transform)
+            }
+            None => false,
+        }
+    }
+
+    pub fn rotate_wall(&mut self, id: WallId, angle: f32) -> bool {
+        match self.wall_transform(id) {
+            Some(mut transform) => {
+                transform.rotation += angle;
+                self.set_wall_transform(id, transform)
+            }
+            None => false,
+        }
+    }
+
+    fn refit_wall(&mut self, id: WallId) {
+        let wall = self.walls.get_mut(id.0).unwrap();
+        let pivot = wall.base.midpoint();
+        let transform = wall.transform;
+        wall.line = wall.base.transformed(|p| transform.apply(pivot, p));
+        let (proxy, bounds) = (wall.proxy, wall.line.bounds());
+        self.tree.update(proxy, bounds);
+    }
+}
+
+/// An axis aligned box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    pub fn around(points: &[Point]) -> Option<Bounds> {
+        let first = *points.first()?;
+        Some(points.iter().fold(
+            Bounds {
+                min: first,
+                max: first,
+            },
+            |b, p| Bounds {
+                min: (b.min.x.min(p.x), b.min.y.min(p.y)).into(),
+                max: (b.max.x.max(p.x), b.max.y.max(p.y)).into(),
+            },
+        ))
+    }
+
+    pub fn overlaps(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    pub fn contains(&self, other: &Bounds) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && other.max.x <= self.max.x
+            && other.max.y <= self.max.y
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: (self.min.x.min(other.min.x), self.min.y.min(other.min.y)).into(),
+            max: (self.max.x.max(other.max.x), self.max.y.max(other.max.y)).into(),
+        }
+    }
+
+    pub fn grow(&self, by: f32) -> Bounds {
+        Bounds {
+            min: (self.min.x - by, self.min.y - by).into(),
+            max: (self.max.x + by, self.max.y + by).into(),
+        }
+    }
+
+    pub fn perimeter(&self) -> f32 {
+        2.0 * ((self.max.x - self.min.x) + (self.max.y - self.min.y))
+    }
+
+    /// Slab test for whether the segment from `start` to `end` passes
+    /// through the box.
+    pub fn intersects_segment(&self, start: Point, end: Point) -> bool {
+        let mut t_min = 0.0f32;
+        let mut t_max = 1.0f32;
+
+        let axes = [
+            (start.x, end.x - start.x, self.min.x, self.max.x),
+            (start.y, end.y - start.y, self.min.y, self.max.y),
+        ];
+        for (from, delta, min, max) in axes {
+            if delta == 0.0 {
+                if from < min || from > max {
+                    return false;
+                }
+                continue;
+            }
+
+            let t1 = (min - from) / delta;
+            let t2 = (max - from) / delta;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+            if t_min > t_max {
+                return false;
+            }
         }
+        true
     }
 }
 
@@ -126,6 +420,10 @@ struct NormalLine {
     y_intercept: f32,
     min_x: f32,
     max_x: f32,
+    // The ends are kept as they were given, since working them out from
+    // the slope loses a lot of precision on steep lines
+    left_y: f32,
+    right_y: f32,
     left_to_right: bool
 }
 
@@ -149,6 +447,7 @@ pub struct Line {
 }
 
 impl Line {
+    #[allow(dead_code)]
     pub fn new(x1: f32, y1: f32, x2: f32, y2: f32) -> Line {
         let inner = LineInner::from_points((x1, y1).into(), (x2, y2).into());
         Self {
@@ -156,6 +455,10 @@ impl Line {
         }
     }
 
+    /// A line so steep that its slope does not fit in an `f32` is made
+    /// vertical at `start.x`, so [`Line::end`] can have a slightly
+    /// different x from `end`. They are less than `|end.y - start.y| /
+    /// f32::MAX` apart.
     pub fn from_points(start: Point, end: Point) -> Line {
         let inner = LineInner::from_points(start, end);
         Self {
@@ -164,29 +467,145 @@ impl Line {
     }
 
 
-    line_inner!(mut offset(x: f32, y: f32));
+    pub fn offset(&mut self, x: f32, y: f32) {
+        self.inner = self.inner.transformed(|p| p + (x, y).into());
+    }
+
+    /// The same line with both ends moved by `f`, keeping its direction.
+    pub fn transformed(&self, f: impl Fn(Point) -> Point) -> Line {
+        Self {
+            inner: self.inner.transformed(f)
+        }
+    }
+
+    pub fn midpoint(&self) -> Point {
+        let (start, end) = (self.start(), self.end());
+        ((start.x + end.x) / 2.0, (start.y + end.y) / 2.0).into()
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds::around(&[self.start(), self.end()]).unwrap()
+    }
 
     line_inner!(nonmut renderable(col: graphics::Color) -> (graphics::Vertex, graphics::Vertex));
 
+    /// Where the lines first meet, going from the start of this one. Hits
+    /// just past the end of either line because of rounding still count,
+    /// moved onto the end, so that rays can not slip through where two
+    /// walls meet. Lines along the same infinite line (apart from rounding)
+    /// meet at the first point they overlap.
+    #[allow(dead_code)]
     pub fn point_of_intersection(&self, other: &Line) -> Option<Point> {
         self.inner.point_of_intersection(&other.inner)
     }
 
+    pub fn start(&self) -> Point {
+        self.inner.start()
+    }
+
+    pub fn end(&self) -> Point {
+        self.inner.end()
+    }
+
+    /// The direction a quarter turn round from the line's, one long, or
+    /// `None` for a line with no length, which has no direction.
+    pub fn unit_normal(&self) -> Option<Point> {
+        let along = self.end() - self.start();
+        let length = along.x.hypot(along.y);
+        (length > 0.0).then(|| (-along.y / length, along.x / length).into())
+    }
+
+    /// How far `point` is from the nearest point on the line.
+    pub fn distance_to_point(&self, point: Point) -> f32 {
+        let (start, end) = (self.start(), self.end());
+        let along = end - start;
+        let length_sq = along.x * along.x + along.y * along.y;
+        let t = if length_sq == 0.0 {
+            0.0
+        } else {
+            (((point.x - start.x) * along.x + (point.y - start.y) * along.y) / length_sq).clamp(0.0, 1.0)
+        };
+        (start.x + along.x * t - point.x).hypot(start.y + along.y * t - point.y)
+    }
+
+    /// How far apart the nearest points on two lines are, which is 0 if
+    /// they cross.
+    pub fn distance_to_line(&self, other: &Line) -> f32 {
+        let (a, b, c, d) = (self.start(), self.end(), other.start(), other.end());
+        let side = |p: Point, q: Point, r: Point| (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+        let (s1, s2) = (side(a, b, c), side(a, b, d));
+        let (s3, s4) = (side(c, d, a), side(c, d, b));
+        if s1 * s2 < 0.0 && s3 * s4 < 0.0 {
+            return 0.0;
+        }
+        self.distance_to_point(c)
+            .min(self.distance_to_point(d))
+            .min(other.distance_to_point(a))
+            .min(other.distance_to_point(b))
+    }
+
     pub fn cast_in_scene(&self, scene: &Scene) -> Self {
-        let inner = self.inner.cast_in_scene(scene);
+        self.cast_in_scene_with_stats(scene, &mut Stats::new())
+    }
+
+    /// Like [`Line::cast_in_scene`], but counts the work it did in `stats`.
+    pub fn cast_in_scene_with_stats(&self, scene: &Scene, stats: &mut Stats) -> Self {
+        let inner = self.inner.cast_in_scene(scene, stats);
         Self {
             inner
         }
     }
+
+    /// Like [`Line::cast_in_scene_with_stats`], but going straight through
+    /// the wall at `passed` and also giving which wall the line stopped at.
+    pub fn cast_past(&self, scene: &Scene, passed: Option<WallId>, stats: &mut Stats) -> (Line, Option<WallId>) {
+        let (inner, hit) = self.inner.cast_past(scene, stats, passed.map(|id| id.0));
+        (Self { inner }, hit.map(WallId))
+    }
+
+    /// Casts the line on through every glass wall it hits, until it hits
+    /// a wall that stops light or gets to its end. Gives how far along the
+    /// line each stretch of it between glass walls goes, and what the glass
+    /// before that stretch tints light by.
+    pub fn cast_through_glass(&self, scene: &Scene, stats: &mut Stats) -> Vec<(f32, Colour)> {
+        let start = self.start();
+        let distance = |p: Point| (p.x - start.x).hypot(p.y - start.y);
+
+        let mut stretches = Vec::new();
+        let mut tint = Colour::WHITE;
+        let mut ray = self.inner.clone();
+        let mut passed = None;
+        for _ in 0..MAX_GLASS {
+            let (cast, hit) = ray.cast_past(scene, stats, passed);
+            stretches.push((distance(cast.end()), tint));
+
+            let glass = hit.and_then(|index| scene.material(scene.walls.get(index).unwrap().material).tint);
+            match glass {
+                Some(glass) if tint != Colour::BLACK => {
+                    tint = tint * glass;
+                    ray = LineInner::from_points(cast.end(), self.end());
+                    passed = hit;
+                }
+                _ => break,
+            }
+        }
+        stretches
+    }
 }
 
+/// How many glass walls light can go through before it is treated as
+/// stopped, so that rays stuck between glass walls meeting at a point
+/// can not go on forever
+pub(crate) const MAX_GLASS: usize = 32;
+
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub struct Point {
-    x: f32,
-    y: f32
+    pub x: f32,
+    pub y: f32
 }
 
 impl Point {
+    #[allow(dead_code)]
     pub fn min_by_x(self, other: Point) -> Point {
         if self.x < other.x {
             self
@@ -196,6 +615,17 @@ impl Point {
     }
 }
 
+impl ops::Sub for Point {
+    type Output = Point;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Point {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y
+        }
+    }
+}
+
 impl ops::Add for Point {
     type Output = Point;
 
@@ -207,6 +637,12 @@ impl ops::Add for Point {
     }
 }
 
+impl From<Point> for system::Vector2f {
+    fn from(point: Point) -> Self {
+        system::Vector2f::new(point.x, point.y)
+    }
+}
+
 impl From<(f32, f32)> for Point {
     fn from((x, y): (f32, f32)) -> Self {
         assert!(!x.is_nan());
@@ -222,50 +658,106 @@ fn is_between(num: f32, min: f32, max: f32) -> bool {
     min <= num && num <= max
 }
 
+/// Like [`is_between`], but lets `num` be just outside because of rounding.
+fn is_nearly_between(num: f32, min: f32, max: f32) -> bool {
+    (min <= num || nearly_equal(num, min)) && (num <= max || nearly_equal(num, max))
+}
+
+/// Whether two numbers are the same apart from rounding.
+fn nearly_equal(a: f32, b: f32) -> bool {
+    (a - b).abs() <= 1e-5 * a.abs().max(b.abs()).max(1.0)
+}
+
 
 impl LineInner {
+    #[allow(dead_code)]
     pub fn new(x1: f32, y1: f32, x2: f32, y2: f32) -> LineInner {
         LineInner::from_points((x1, y1).into(), (x2, y2).into())
     }
 
+    /// Lines so steep that their slope does not fit in a float are made
+    /// vertical, at the x of `start`.
     pub fn from_points(start: Point, end: Point) -> LineInner {
-        if start.x == end.x {
-            assert_eq!(start.x, end.x);
-            Self::Vertical(VerticalLine::from_points(start, end))
+        if start.x == end.x || !NormalLine::slope_between(start, end).is_finite() {
+            Self::Vertical(VerticalLine::from_points(start, (start.x, end.y).into()))
         } else {
-            assert_ne!(start.x, end.x);
             Self::Normal(NormalLine::from_points(start, end))
         }
     }
 
-    pub fn offset(&mut self, x: f32, y: f32) {
-        let t = match self {
-            LineInner::Normal(v) => v.offset(x, y),
-            LineInner::Vertical(v) => v.offset(x, y),
-        };
-
-        *self = t;
+    fn transformed(&self, f: impl Fn(Point) -> Point) -> LineInner {
+        // Going through `LineInner::from_points` means a normal line can
+        // become a vertical one (or the other way around) if the ends
+        // end up with the same x after being moved. This can happen for
+        // normal lines that are nearly vertical, where the floats round
+        // to the same number.
+        LineInner::from_points(f(self.start()), f(self.end()))
     }
 
     line_impl!(nonmut renderable(col: graphics::Color) -> (graphics::Vertex, graphics::Vertex));
 
     line_impl!(nonmut point_of_intersection(other: &LineInner) -> Option<Point>);
 
-    pub fn cast_in_scene(&self, scene: &Scene) -> LineInner {
-        match self.clone() {
-            Self::Vertical(mut v) => {
-                for wall in &scene.walls {
-                    v = v.cast_to_line(&wall.inner);
-                }
-                Self::Vertical(v)
+    pub fn start(&self) -> Point {
+        match self {
+            Self::Vertical(v) if v.down_to_up => v.bottom_point(),
+            Self::Vertical(v) => v.top_point(),
+            Self::Normal(n) if n.left_to_right => n.left_point(),
+            Self::Normal(n) => n.right_point(),
+        }
+    }
+
+    pub fn end(&self) -> Point {
+        match self {
+            Self::Vertical(v) if v.down_to_up => v.top_point(),
+            Self::Vertical(v) => v.bottom_point(),
+            Self::Normal(n) if n.left_to_right => n.right_point(),
+            Self::Normal(n) => n.left_point(),
+        }
+    }
+
+    pub fn cast_in_scene(&self, scene: &Scene, stats: &mut Stats) -> LineInner {
+        self.cast_past(scene, stats, None).0
+    }
+
+    /// Like [`LineInner::cast_in_scene`], but going straight through the
+    /// wall at `passed` and also giving which wall the line stopped at.
+    fn cast_past(&self, scene: &Scene, stats: &mut Stats, passed: Option<Index>) -> (LineInner, Option<Index>) {
+        // Every hit shortens the line, so the bounds check is done against
+        // the line as it is so far and walls further away get skipped.
+        let line = RefCell::new(self.clone());
+        let stopped_at = Cell::new(None);
+        stats.rays_cast += 1;
+        let nodes = scene.tree.visit(
+            |bounds| {
+                let line = line.borrow();
+                bounds.intersects_segment(line.start(), line.end())
             },
-            Self::Normal(mut v) => {
-                for wall in &scene.walls {
-                    v = v.cast_to_line(&wall.inner);
+            |index| {
+                if Some(index) == passed {
+                    return;
+                }
+                let wall = &scene.walls.get(index).unwrap().line;
+                stats.intersection_tests += 1;
+                let cast = line.borrow().cast_to_line(&wall.inner);
+                if let Some(cast) = cast {
+                    stats.hits += 1;
+                    stopped_at.set(Some(index));
+                    *line.borrow_mut() = cast;
                 }
-                Self::Normal(v)
             },
-        }
+        );
+        stats.nodes_visited += nodes as u64;
+        (line.into_inner(), stopped_at.get())
+    }
+
+    /// The line cut short where it hits `other`, if it does.
+    fn cast_to_line(&self, other: &LineInner) -> Option<LineInner> {
+        // Going through `LineInner::from_points` means a ray that is
+        // stopped right at its start becomes a zero length vertical line
+        // instead of a normal line with no width.
+        self.point_of_intersection(other)
+            .map(|intersection| LineInner::from_points(self.start(), intersection))
     }
 }
 
@@ -291,18 +783,6 @@ impl VerticalLine {
             down_to_up,
         }
     }
-    fn offset(&self, x: f32, y: f32) -> LineInner {
-
-        let new_vert = VerticalLine {
-            x: self.x + x,
-            max_y: self.max_y + y,
-            min_y: self.min_y + y,
-            down_to_up: self.down_to_up
-        };
-
-        LineInner::Vertical(new_vert)
-    }
-
     fn renderable(&self, col: graphics::Color) -> (graphics::Vertex, graphics::Vertex) {
         let first_point = graphics::Vertex::new(
             system::Vector2f::new(self.x, self.min_y),
@@ -325,18 +805,6 @@ impl VerticalLine {
         (self.x, self.max_y).into()
     }
 
-    fn cast_to_line(&self, other: &LineInner) -> VerticalLine {
-        if let Some(intersection) = self.point_of_intersection(other) {
-            if self.down_to_up {
-                Self::from_points(self.bottom_point(), intersection)
-            } else {
-                Self::from_points(self.top_point(), intersection)
-            }
-        } else {
-            self.clone()
-        }
-    }
-
     fn point_of_intersection(&self, other: &LineInner) -> Option<Point> {
         match other {
             LineInner::Vertical(v) => self.point_of_intersection_vert(v),
@@ -348,22 +816,20 @@ impl VerticalLine {
         other.point_of_intersection_vertical(self)
     }
 
+    /// Where the lines first overlap, going from the start of this one.
     fn point_of_intersection_vert(&self, other: &VerticalLine) -> Option<Point> {
         if self.x != other.x {
             return None
         }
 
-        // TODO: This does not actually follow the line up/down
-        // So if the first line is (0, 10) -> (0, 0) and the second
-        // is (0, 1) -> (0, 2), this algorithm will still say
-        // that the first point of intersection is (0, 1), when it
-        // should be (0, 2)
-        if is_between(other.min_y, self.min_y, self.max_y) {
-            Some((self.x, other.min_y).into())
-        } else if is_between(other.max_y, self.min_y, self.max_y) {
-            Some((self.x, other.max_y).into())
-        } else {
+        let bottom = self.min_y.max(other.min_y);
+        let top = self.max_y.min(other.max_y);
+        if bottom > top {
             None
+        } else if self.down_to_up {
+            Some((self.x, bottom).into())
+        } else {
+            Some((self.x, top).into())
         }
     }
 }
@@ -382,7 +848,7 @@ impl NormalLine {
 
         assert_ne!(start_x, end_x);
 
-        let slope = (end_y - start_y) / (end_x - start_x);
+        let slope = Self::slope_between(start, end);
 
         let y_intercept = (-slope * start_x) + start_y;
 
@@ -392,6 +858,8 @@ impl NormalLine {
                 y_intercept,
                 min_x: end_x,
                 max_x: start_x,
+                left_y: end_y,
+                right_y: start_y,
                 left_to_right: false,
             }
         } else {
@@ -400,58 +868,25 @@ impl NormalLine {
                 y_intercept,
                 min_x: start_x,
                 max_x: end_x,
+                left_y: start_y,
+                right_y: end_y,
                 left_to_right: true,
             }
         }
     }
 
-    fn left_point(&self) -> Point {
-        self.point_at(self.min_x).unwrap()
+    /// The slope of the line between two points. Both are halved first so
+    /// that the differences can not overflow for huge coordinates.
+    fn slope_between(start: Point, end: Point) -> f32 {
+        (end.y * 0.5 - start.y * 0.5) / (end.x * 0.5 - start.x * 0.5)
     }
 
-    fn right_point(&self) -> Point {
-        self.point_at(self.max_x).unwrap()
-    }
-
-    fn offset(&self, x: f32, y: f32) -> LineInner {
-        let offset = (x, y).into();
-        let lhs = self.left_point() + offset;
-        let rhs = self.right_point() + offset;
-
-        // TODO: Normal lines becoming vertical in NormalLine::offset
-        // If the minimum and maximum x values are close enough, nor-
-        // mal lines can become Vertical lines after an offset as the
-        // floats round to the same number. Currently we are support-
-        // ing this behaviour but it should not happen, and could
-        // start affecting more lines as the offsets get bigger.
-        if lhs.x == rhs.x {
-            let vert_line = if self.left_to_right {
-                VerticalLine::from_points(lhs, rhs)
-            } else {
-                VerticalLine::from_points(rhs, lhs)
-            };
-            LineInner::Vertical(vert_line)
-        } else {
-            let normal_line = if self.left_to_right {
-                NormalLine::from_points(lhs, rhs)
-            } else {
-                NormalLine::from_points(rhs, lhs)
-            };
-
-            LineInner::Normal(normal_line)
-        }
+    fn left_point(&self) -> Point {
+        (self.min_x, self.left_y).into()
     }
 
-    fn cast_to_line(&self, other: &LineInner) -> NormalLine {
-        if let Some(intersection) = self.point_of_intersection(other) {
-            if self.left_to_right {
-                NormalLine::from_points(self.left_point(), intersection)
-            } else {
-                NormalLine::from_points(self.right_point(), intersection)
-            }
-        } else {
-            self.clone()
-        }
+    fn right_point(&self) -> Point {
+        (self.max_x, self.right_y).into()
     }
 
     fn point_of_intersection(&self, other: &LineInner) -> Option<Point> {
@@ -468,33 +903,53 @@ impl NormalLine {
 
         let y = self.y_at(other.x).unwrap();
 
-        if !is_between(y, other.min_y, other.max_y) {
+        if !is_nearly_between(y, other.min_y, other.max_y) {
             return None;
         }
 
-        Some((other.x, y).into())
+        Some((other.x, y.clamp(other.min_y, other.max_y)).into())
     }
 
     fn point_of_intersection_normal(&self, other: &NormalLine) -> Option<Point> {
-        if self.slope == other.slope {
-
-            if self.y_intercept != other.y_intercept {
-                return None
-            }
-
-            if is_between(self.min_x, other.min_x, other.max_x) {
-                return self.point_at(self.min_x)
+        // Lines on the same infinite line (apart from rounding) meet where
+        // they first overlap going from the start of this one
+        if self.nearly_through(other.left_point()) && self.nearly_through(other.right_point()) {
+            let left = self.min_x.max(other.min_x);
+            let right = self.max_x.min(other.max_x);
+            return match (left > right, self.left_to_right) {
+                (true, _) => None,
+                (false, true) => self.point_at(left),
+                (false, false) => self.point_at(right),
             }
+        }
 
-            if is_between(self.max_x, other.min_x, other.max_x) {
-                return self.point_at(self.max_x)
-            }
+        if self.slope == other.slope {
+            return None
         }
 
         let x = (other.y_intercept - self.y_intercept) / (self.slope - other.slope);
 
-        if is_between(x, other.min_x, other.max_x) {
-            self.point_at(x)
+        // Rounding can put a hit right at the end of a line just past it,
+        // which would let rays slip through where two walls meet
+        let left = self.min_x.max(other.min_x);
+        let right = self.max_x.min(other.max_x);
+        if left > right || !is_nearly_between(x, left, right) {
+            return None
+        }
+        let x = x.clamp(left, right);
+
+        // A small error in x is a big one in y on a steep line, so y comes
+        // from the flatter of the two
+        let flatter = if self.slope.abs() <= other.slope.abs() { self } else { other };
+        let y = flatter.y_at(x).unwrap();
+
+        // On a steep line the slack in x is a long way in y, so y has to be
+        // in range as well
+        let in_range = |line: &NormalLine| {
+            is_nearly_between(y, line.left_y.min(line.right_y), line.left_y.max(line.right_y))
+        };
+        if in_range(self) && in_range(other) {
+            Some((x, y).into())
         } else {
             None
         }
@@ -525,10 +980,30 @@ impl NormalLine {
     fn y_at(&self, x: f32) -> Option<f32> {
         assert!(!x.is_nan());
 
-        if is_between(x, self.min_x, self.max_x) {
-            Some(self.slope * x + self.y_intercept)
+        if x == self.min_x {
+            Some(self.left_y)
+        } else if x == self.max_x {
+            Some(self.right_y)
+        } else if is_between(x, self.min_x, self.max_x) {
+            Some(self.y_at_unbounded(x))
         } else {
             None
         }
     }
+
+    /// Whether `p` is on the infinite line through this one, apart from
+    /// rounding. The rounding in the slope adds up the further it is
+    /// followed, so the further away `p` is the more it is let off.
+    fn nearly_through(&self, p: Point) -> bool {
+        let gap = self.y_at_unbounded(p.x) - p.y;
+        let reach = (self.slope * (p.x - self.min_x)).abs();
+        gap.abs() <= 1e-4 * reach.max(self.left_y.abs()).max(p.y.abs()).max(1.0)
+    }
+
+    /// Where the infinite line through this one is at `x`. It goes from the
+    /// left end rather than the y intercept, which can be huge for steep
+    /// lines, and halves the distance so that it can not overflow.
+    fn y_at_unbounded(&self, x: f32) -> f32 {
+        self.left_y + self.slope * (x * 0.5 - self.min_x * 0.5) * 2.0
+    }
 }
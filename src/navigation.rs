@@ -0,0 +1,221 @@
+//! Paths round the walls of a scene, for things moving through it.
+//!
+//! The shortest way round walls always bends at their corners, so
+//! [`Scene::line_of_sight_graph`] puts points just off every corner that
+//! sticks out and joins up the ones with a clear line between them. Giving
+//! it a radius keeps everything that far from the walls, so that something
+//! round of that size can follow the paths without scraping along them.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    f32::consts::{FRAC_PI_2, PI, TAU},
+};
+
+use crate::math::{Bounds, Line, Point, Scene};
+
+/// How much further than the radius from the walls the points are put, so
+/// that rounding can not make them too close
+const CLEARANCE_MARGIN: f32 = 0.01;
+
+/// How close two wall ends have to be to count as the same corner
+const SAME_CORNER: f32 = 1e-3;
+
+/// The widest angle round a corner between two neighbouring points, so
+/// that the line between them stays clear of the corner
+const MAX_STEP: f32 = FRAC_PI_2;
+
+/// Points off the corners of a scene, joined up wherever something of
+/// `radius` can get straight from one to the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavGraph {
+    pub radius: f32,
+    pub nodes: Vec<Point>,
+    /// For every node, the nodes it is joined to and how far away they are
+    pub edges: Vec<Vec<(usize, f32)>>,
+}
+
+impl Scene {
+    /// The visibility graph of the wall corners, for something of
+    /// `radius`. It has to be made again when walls move.
+    pub fn line_of_sight_graph(&self, radius: f32) -> NavGraph {
+        let mut graph = NavGraph {
+            radius,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        graph.nodes = corners(self)
+            .into_iter()
+            .flat_map(|(corner, directions)| corner_nodes(corner, &directions, radius))
+            .filter(|node| graph.is_clear(self, *node, *node))
+            .collect();
+        graph.edges = vec![Vec::new(); graph.nodes.len()];
+
+        for a in 0..graph.nodes.len() {
+            for b in a + 1..graph.nodes.len() {
+                let (from, to) = (graph.nodes[a], graph.nodes[b]);
+                if graph.is_clear(self, from, to) {
+                    let distance = distance(from, to);
+                    graph.edges[a].push((b, distance));
+                    graph.edges[b].push((a, distance));
+                }
+            }
+        }
+        graph
+    }
+}
+
+impl NavGraph {
+    /// Whether something of the graph's radius can go straight from `from`
+    /// to `to` in `scene` without touching a wall.
+    pub fn is_clear(&self, scene: &Scene, from: Point, to: Point) -> bool {
+        let clearance = self.radius + CLEARANCE_MARGIN / 2.0;
+        let bounds = Bounds::around(&[from, to]).unwrap().grow(clearance);
+        let walls = scene.walls_near(&bounds);
+        if from == to {
+            return walls.into_iter().all(|(_, wall)| wall.distance_to_point(from) >= clearance);
+        }
+        let path = Line::from_points(from, to);
+        walls.into_iter().all(|(_, wall)| path.distance_to_line(wall) >= clearance)
+    }
+
+    /// The shortest way from `from` to `to` round the walls of `scene`,
+    /// which has to be the scene the graph was made from, found with A*.
+    /// Gives every point the path bends at, starting with `from` and ending
+    /// with `to`, or `None` if there is no way there.
+    pub fn shortest_path(&self, scene: &Scene, from: Point, to: Point) -> Option<Vec<Point>> {
+        if self.is_clear(scene, from, to) {
+            return Some(vec![from, to]);
+        }
+
+        // The start and goal go after the graph's own nodes
+        let (start, goal) = (self.nodes.len(), self.nodes.len() + 1);
+        let point = |node: usize| match node {
+            node if node == start => from,
+            node if node == goal => to,
+            node => self.nodes[node],
+        };
+        let from_start = self.joined_to(scene, from);
+        let to_goal = self.joined_to(scene, to);
+        let neighbours = |node: usize| {
+            let mut neighbours = match node {
+                node if node == start => from_start.clone(),
+                node => self.edges[node].clone(),
+            };
+            if let Some(&(_, distance)) = to_goal.iter().find(|(n, _)| *n == node) {
+                neighbours.push((goal, distance));
+            }
+            neighbours
+        };
+
+        let mut best = vec![f32::INFINITY; self.nodes.len() + 2];
+        let mut came_from = vec![None; self.nodes.len() + 2];
+        let mut open = BinaryHeap::new();
+        best[start] = 0.0;
+        open.push(Open { estimate: distance(from, to), node: start });
+
+        while let Some(Open { node, .. }) = open.pop() {
+            if node == goal {
+                let mut path = vec![to];
+                let mut node = goal;
+                while let Some(previous) = came_from[node] {
+                    path.push(point(previous));
+                    node = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (next, step) in neighbours(node) {
+                let so_far = best[node] + step;
+                if so_far < best[next] {
+                    best[next] = so_far;
+                    came_from[next] = Some(node);
+                    open.push(Open { estimate: so_far + distance(point(next), to), node: next });
+                }
+            }
+        }
+        None
+    }
+
+    /// The nodes with a clear line to `point`, and how far away they are.
+    fn joined_to(&self, scene: &Scene, point: Point) -> Vec<(usize, f32)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| self.is_clear(scene, point, **node))
+            .map(|(i, node)| (i, distance(point, *node)))
+            .collect()
+    }
+}
+
+/// A node waiting to be looked at, ordered so the heap gives the one with
+/// the lowest estimate first.
+#[derive(Debug, PartialEq)]
+struct Open {
+    estimate: f32,
+    node: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+/// Every wall end, with the angles of the walls going out from it.
+fn corners(scene: &Scene) -> Vec<(Point, Vec<f32>)> {
+    let mut corners: Vec<(Point, Vec<f32>)> = Vec::new();
+    for wall in scene.walls() {
+        for (corner, other) in [(wall.start(), wall.end()), (wall.end(), wall.start())] {
+            let angle = (other.y - corner.y).atan2(other.x - corner.x);
+            match corners.iter_mut().find(|(c, _)| distance(*c, corner) <= SAME_CORNER) {
+                Some((_, angles)) => angles.push(angle),
+                None => corners.push((corner, vec![angle])),
+            }
+        }
+    }
+    corners
+}
+
+/// The points to go round `corner` by, in every gap between the walls
+/// going out from it that is wider than a straight line. They are spread
+/// round the corner far enough out that the lines between them stay
+/// `radius` from it.
+fn corner_nodes(corner: Point, angles: &[f32], radius: f32) -> Vec<Point> {
+    let mut angles = angles.to_vec();
+    angles.sort_by(f32::total_cmp);
+    let mut nodes = Vec::new();
+    for (i, &angle) in angles.iter().enumerate() {
+        let next = match angles.get(i + 1) {
+            Some(&next) => next,
+            None => angles[0] + TAU,
+        };
+        // Only the part of the gap where the corner itself is the nearest
+        // bit of wall, square on to the walls either side of it
+        let open = next - angle - PI;
+        if open <= 0.0 {
+            continue;
+        }
+        let steps = (open / MAX_STEP).ceil().max(1.0) as usize;
+        let step = open / steps as f32;
+        let reach = (radius + CLEARANCE_MARGIN) / (step / 2.0).cos();
+        let first = angle + FRAC_PI_2;
+        nodes.extend((0..=steps).map(|s| {
+            let at = first + step * s as f32;
+            corner + (at.cos() * reach, at.sin() * reach).into()
+        }));
+    }
+    nodes
+}
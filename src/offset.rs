@@ -0,0 +1,350 @@
+//! Walls with some thickness to them, for things bigger than a point.
+//!
+//! A circle of some radius can only get as close to a wall as that radius,
+//! so where it can go is where a point could go if every wall were
+//! inflated by the radius. [`Scene::inflated`] does that, giving a new
+//! scene of outlines round the walls, and [`Line::cast_thick`] casts a
+//! circle through the scene without making a new one.
+
+use std::f32::consts::PI;
+
+use crate::{
+    material::MaterialId,
+    math::{Line, Point, Scene, WallId},
+    stats::Stats,
+};
+
+/// How the outline goes round a corner that sticks out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Join {
+    /// Round the corner, the same distance from it all the way
+    Round,
+    /// Straight on along both sides until they meet, squared off at the
+    /// radius if that would be more than [`MITER_LIMIT`] times as far
+    Miter,
+}
+
+/// How far a miter join can stick out before it is squared off, as a
+/// multiple of the radius
+pub const MITER_LIMIT: f32 = 4.0;
+
+/// The widest angle round a corner one straight bit of a round join goes
+const ROUND_STEP: f32 = PI / 8.0;
+
+/// How close two wall ends have to be to count as joined
+const SAME_CORNER: f32 = 1e-3;
+
+/// `points` going round a closed polygon moved out by `distance`, or in if
+/// it is negative, whichever way round the polygon goes. The polygon can
+/// go in as well as out, but where it does the outline crosses itself.
+pub fn offset_polygon(points: &[Point], distance: f32, join: Join) -> Vec<Point> {
+    let points = without_repeats(points, true);
+    offset_loop(&points, distance * signed_area(&points).signum(), join)
+}
+
+/// The outline of everything within `radius` of the line through `points`,
+/// with ends rounded or squared off to match `join`.
+pub fn offset_polyline(points: &[Point], radius: f32, join: Join) -> Vec<Point> {
+    let points = without_repeats(points, false);
+    if points.len() < 2 {
+        return Vec::new();
+    }
+    // There and back again, so that the ends are corners turning right
+    // round and one side of the loop is each side of the line
+    let mut there_and_back = points.clone();
+    there_and_back.extend(points.iter().rev().skip(1).take(points.len() - 2));
+    offset_loop(&there_and_back, radius.abs(), join)
+}
+
+impl Scene {
+    /// A new scene with every wall replaced by the outline of everything
+    /// within `radius` of it, so walls joined end to end give one outline
+    /// and closed shapes give one outline outside and one inside them.
+    /// Walls keep their material. Moving walls are inflated where they are
+    /// now.
+    pub fn inflated(&self, radius: f32, join: Join) -> Scene {
+        let mut inflated = Scene::new();
+        for (id, material) in self.materials() {
+            if id == MaterialId::DEFAULT {
+                *inflated.material_mut(id) = material.clone();
+            } else {
+                inflated.add_material(material.clone());
+            }
+        }
+
+        let radius = radius.abs();
+        for (points, closed, material) in chains(self) {
+            let outlines = if closed {
+                let inside = offset_polygon(&points, -radius, join);
+                // Nothing is left inside a shape thinner than the radius,
+                // and what offsetting gives is inside out and too close
+                let sides = (0..points.len())
+                    .map(|i| Line::from_points(points[i], points[(i + 1) % points.len()]))
+                    .collect::<Vec<_>>();
+                let clear = |p: &Point| sides.iter().all(|side| side.distance_to_point(*p) >= radius * 0.999);
+                let mut outlines = vec![offset_polygon(&points, radius, join)];
+                if inside.iter().all(clear) {
+                    outlines.push(inside);
+                }
+                outlines
+            } else {
+                vec![offset_polyline(&points, radius, join)]
+            };
+
+            for outline in outlines {
+                for (i, &start) in outline.iter().enumerate() {
+                    let end = outline[(i + 1) % outline.len()];
+                    if start != end {
+                        let wall = inflated.add_line_no_graphics(start, end);
+                        inflated.set_wall_material(wall, material);
+                    }
+                }
+            }
+        }
+        inflated
+    }
+}
+
+impl Line {
+    /// Like [`Line::cast_past`], but for a circle of `radius` going along
+    /// the line, stopping where it first touches a wall. Gives the line up
+    /// to where the middle of the circle got.
+    pub fn cast_thick(&self, scene: &Scene, radius: f32, stats: &mut Stats) -> (Line, Option<WallId>) {
+        let (start, end) = (self.start(), self.end());
+        let along = end - start;
+        stats.rays_cast += 1;
+
+        let mut first: Option<(f32, WallId)> = None;
+        for (id, wall) in scene.walls_near(&self.bounds().grow(radius)) {
+            stats.intersection_tests += 1;
+            let Some(t) = touches(start, along, wall, radius) else {
+                continue;
+            };
+            if first.is_none_or(|(nearest, _)| t < nearest) {
+                stats.hits += 1;
+                first = Some((t, id));
+            }
+        }
+
+        match first {
+            Some((t, id)) => {
+                let stop = start + (along.x * t, along.y * t).into();
+                (Line::from_points(start, stop), Some(id))
+            }
+            None => (self.clone(), None),
+        }
+    }
+}
+
+/// How far from 0 to 1 along `along` a circle of `radius` starting at
+/// `start` gets before it touches `wall`, if it does.
+fn touches(start: Point, along: Point, wall: &Line, radius: f32) -> Option<f32> {
+    if wall.distance_to_point(start) <= radius {
+        return Some(0.0);
+    }
+
+    let (a, b) = (wall.start(), wall.end());
+    let side = b - a;
+    let length = side.x.hypot(side.y);
+    let mut hits = Vec::new();
+
+    // Either side of the wall
+    if length > 0.0 {
+        let unit = Point::from((side.x / length, side.y / length));
+        let normal = Point::from((-unit.y, unit.x));
+        let towards = dot(along, normal);
+        if towards != 0.0 {
+            for offset in [radius, -radius] {
+                let t = (offset - dot(start - a, normal)) / towards;
+                let centre = start + (along.x * t, along.y * t).into();
+                if (0.0..=length).contains(&dot(centre - a, unit)) {
+                    hits.push(t);
+                }
+            }
+        }
+    }
+
+    // Either end of it
+    for corner in [a, b] {
+        let to_start = start - corner;
+        let (qa, qb) = (dot(along, along), 2.0 * dot(along, to_start));
+        let qc = dot(to_start, to_start) - radius * radius;
+        let discriminant = qb * qb - 4.0 * qa * qc;
+        if qa > 0.0 && discriminant >= 0.0 {
+            hits.push((-qb - discriminant.sqrt()) / (2.0 * qa));
+        }
+    }
+
+    hits.into_iter()
+        .filter(|t| (0.0..=1.0).contains(t))
+        .min_by(f32::total_cmp)
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+fn unit(p: Point) -> Point {
+    let length = p.x.hypot(p.y);
+    (p.x / length, p.y / length).into()
+}
+
+fn scaled(p: Point, by: f32) -> Point {
+    (p.x * by, p.y * by).into()
+}
+
+/// Twice the area of a polygon, positive if it goes anticlockwise with y
+/// going up.
+fn signed_area(points: &[Point]) -> f32 {
+    (0..points.len())
+        .map(|i| {
+            let (p, q) = (points[i], points[(i + 1) % points.len()]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum()
+}
+
+/// `points` without any that are the same as the one before, and without
+/// the last one if it is the same as the first and `closed`.
+fn without_repeats(points: &[Point], closed: bool) -> Vec<Point> {
+    let mut kept: Vec<Point> = Vec::new();
+    for &p in points {
+        if kept.last() != Some(&p) {
+            kept.push(p);
+        }
+    }
+    if closed && kept.len() > 1 && kept.first() == kept.last() {
+        kept.pop();
+    }
+    kept
+}
+
+/// Moves every edge of the closed loop `points` to its right (with y going
+/// up) by `distance`, and joins them back up.
+fn offset_loop(points: &[Point], distance: f32, join: Join) -> Vec<Point> {
+    let count = points.len();
+    if count < 2 || distance == 0.0 {
+        return points.to_vec();
+    }
+    let radius = distance.abs();
+    // Which way each edge goes, and which way it moves
+    let edges = (0..count)
+        .map(|i| {
+            let direction = unit(points[(i + 1) % count] - points[i]);
+            (direction, scaled((direction.y, -direction.x).into(), distance.signum()))
+        })
+        .collect::<Vec<_>>();
+
+    let mut outline = Vec::new();
+    for i in 0..count {
+        let corner = points[i];
+        let (d0, o0) = edges[(i + count - 1) % count];
+        let (d1, o1) = edges[i];
+        let a = corner + scaled(o0, radius);
+        let b = corner + scaled(o1, radius);
+        let reverses = dot(d0, d1) < -1.0 + 1e-6;
+
+        let cos = dot(o0, o1);
+        let sticks_out = if reverses { f32::INFINITY } else { (2.0 / (1.0 + cos)).sqrt() };
+        if dot(o1, d0).abs() <= 1e-6 && !reverses {
+            outline.push(a);
+            continue;
+        }
+        // Corners turning the other way, where the sides meet inside the
+        // corner, unless it is so sharp that they cross well past it
+        if dot(o1, d0) < 0.0 {
+            if sticks_out <= MITER_LIMIT {
+                outline.push(corner + scaled(o0 + o1, radius / (1.0 + cos)));
+            } else {
+                outline.extend([a, b]);
+            }
+            continue;
+        }
+
+        match join {
+            Join::Round => {
+                let turn = cos.clamp(-1.0, 1.0).acos();
+                let way = if o0.x * d0.y - o0.y * d0.x >= 0.0 { 1.0 } else { -1.0 };
+                let steps = (turn / ROUND_STEP).ceil().max(1.0);
+                let step = turn / steps;
+                // Far enough out that the straight bits do not cut inside
+                // the circle
+                let reach = radius / (step / 2.0).cos();
+                let from = o0.y.atan2(o0.x);
+                outline.push(a);
+                for s in 0..steps as usize {
+                    let angle = from + way * step * (s as f32 + 0.5);
+                    outline.push(corner + (angle.cos() * reach, angle.sin() * reach).into());
+                }
+                outline.push(b);
+            }
+            Join::Miter => {
+                if sticks_out <= MITER_LIMIT {
+                    outline.push(corner + scaled(o0 + o1, radius / (1.0 + cos)));
+                    continue;
+                }
+                // Squared off where it is the radius out from the corner
+                let out = if reverses { d0 } else { unit(o0 + o1) };
+                let t0 = (radius - dot(scaled(o0, radius), out)) / dot(d0, out);
+                let t1 = (radius - dot(scaled(o1, radius), out)) / -dot(d1, out);
+                outline.push(a + scaled(d0, t0));
+                outline.push(b - scaled(d1, t1));
+            }
+        }
+    }
+    outline
+}
+
+/// The walls of `scene` joined up end to end where they are the same
+/// material, with whether each chain closes up on itself.
+fn chains(scene: &Scene) -> Vec<(Vec<Point>, bool, MaterialId)> {
+    let walls = scene
+        .wall_ids()
+        .map(|(id, wall)| (wall.start(), wall.end(), scene.wall_material(id).unwrap()))
+        .collect::<Vec<_>>();
+    let near = |a: Point, b: Point| (a.x - b.x).hypot(a.y - b.y) <= SAME_CORNER;
+    let mut used = vec![false; walls.len()];
+    let mut chains = Vec::new();
+
+    for first in 0..walls.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let (start, end, material) = walls[first];
+        let mut points = vec![start, end];
+
+        // On from the end, and then back from the start
+        for backwards in [false, true] {
+            loop {
+                let last = if backwards { points[0] } else { points[points.len() - 1] };
+                let next = (0..walls.len()).find_map(|i| {
+                    let (a, b, m) = walls[i];
+                    if used[i] || m != material {
+                        None
+                    } else if near(a, last) {
+                        Some((i, b))
+                    } else if near(b, last) {
+                        Some((i, a))
+                    } else {
+                        None
+                    }
+                });
+                let Some((i, point)) = next else { break };
+                used[i] = true;
+                if backwards {
+                    points.insert(0, point);
+                } else {
+                    points.push(point);
+                }
+            }
+        }
+
+        let closed = points.len() > 3 && near(points[0], points[points.len() - 1]);
+        if closed {
+            points.pop();
+        }
+        chains.push((points, closed, material));
+    }
+    chains
+}
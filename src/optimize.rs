@@ -0,0 +1,203 @@
+//! Cleaning up scenes: clicking in the viewer or importing drawings easily
+//! gives walls with no length, walls on top of each other and long chains
+//! of short walls in a straight line, which all make casting slower.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    material::MaterialId,
+    math::{Line, Point, Scene, Transform, WallId},
+};
+
+/// How close endpoints have to be to get welded together, and how far a
+/// merged wall may be from the corners it replaces, in world units.
+pub const DEFAULT_TOLERANCE: f32 = 0.5;
+
+/// What [`Scene::optimize`] changed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptimizeReport {
+    /// Walls that were shorter than the tolerance
+    pub degenerate: usize,
+    /// Walls that had the same ends and material as another wall
+    pub duplicates: usize,
+    /// Walls that were merged into the wall they continued
+    pub merged: usize,
+    /// Endpoints that were moved onto a nearby one
+    pub welded: usize,
+    /// Every wall that is gone from the scene
+    pub removed: Vec<WallId>,
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} degenerate and {} duplicate walls, merged {} walls and welded {} endpoints",
+            self.degenerate, self.duplicates, self.merged, self.welded
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    id: WallId,
+    ends: [Point; 2],
+    material: MaterialId,
+    /// Corners that were merged away, which the wall has to stay close to
+    dropped: Vec<Point>,
+    alive: bool,
+}
+
+fn key(p: Point) -> (u32, u32) {
+    (p.x.to_bits(), p.y.to_bits())
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+impl Scene {
+    pub fn optimize(&mut self, animated: &HashSet<WallId>) -> OptimizeReport {
+        self.optimize_with(DEFAULT_TOLERANCE, animated)
+    }
+
+    /// Welds endpoints closer than `tolerance`, removes walls that end up
+    /// shorter than that or on top of another wall with the same material,
+    /// and merges walls that continue each other in a straight line.
+    ///
+    /// Walls keep their ids unless they are removed. The `animated` walls,
+    /// usually [`Timeline::animated_walls`](crate::timeline::Timeline::animated_walls),
+    /// and walls that have been moved with a transform are left alone, since
+    /// their resting place is what gets animated, even at a moment where
+    /// their keyframes put them back on it.
+    pub fn optimize_with(&mut self, tolerance: f32, animated: &HashSet<WallId>) -> OptimizeReport {
+        let mut report = OptimizeReport::default();
+        let mut walls = self
+            .wall_ids()
+            .filter(|(id, _)| !animated.contains(id))
+            .filter(|(id, _)| self.wall_transform(*id) == Some(Transform::identity()))
+            .map(|(id, line)| Candidate {
+                id,
+                ends: [line.start(), line.end()],
+                material: self.wall_material(id).unwrap(),
+                dropped: Vec::new(),
+                alive: true,
+            })
+            .collect::<Vec<_>>();
+
+        // Every endpoint is snapped to the first endpoint seen within the
+        // tolerance, looking in the neighbouring cells of a grid
+        let cell_size = tolerance.max(f32::EPSILON);
+        let cell = |p: Point| ((p.x / cell_size).floor() as i64, (p.y / cell_size).floor() as i64);
+        let mut grid: HashMap<(i64, i64), Vec<Point>> = HashMap::new();
+        for wall in &mut walls {
+            for end in &mut wall.ends {
+                let (cx, cy) = cell(*end);
+                let nearby = (cx - 1..=cx + 1)
+                    .flat_map(|x| (cy - 1..=cy + 1).map(move |y| (x, y)))
+                    .filter_map(|c| grid.get(&c))
+                    .flatten()
+                    .copied()
+                    .filter(|p| distance(*p, *end) <= tolerance)
+                    .min_by(|a, b| distance(*a, *end).total_cmp(&distance(*b, *end)));
+                match nearby {
+                    Some(p) => {
+                        if p != *end {
+                            report.welded += 1;
+                            *end = p;
+                        }
+                    }
+                    None => grid.entry((cx, cy)).or_default().push(*end),
+                }
+            }
+        }
+
+        for wall in &mut walls {
+            if distance(wall.ends[0], wall.ends[1]) <= tolerance {
+                wall.alive = false;
+                report.degenerate += 1;
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for wall in walls.iter_mut().filter(|wall| wall.alive) {
+            let mut ends = [key(wall.ends[0]), key(wall.ends[1])];
+            ends.sort_unstable();
+            if !seen.insert((ends, wall.material)) {
+                wall.alive = false;
+                report.duplicates += 1;
+            }
+        }
+
+        // Merges the two walls meeting at a corner if nothing else meets
+        // there and the corner is close to the line between their other ends
+        let mut at: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (i, wall) in walls.iter().enumerate().filter(|(_, wall)| wall.alive) {
+            for end in wall.ends {
+                at.entry(key(end)).or_default().push(i);
+            }
+        }
+        // Sorted so the result does not depend on the order of the map
+        let mut corners = at.keys().copied().collect::<Vec<_>>();
+        corners.sort_unstable();
+        for corner in corners {
+            let (a, b) = match at[&corner][..] {
+                [a, b] if a != b && walls[a].material == walls[b].material => (a, b),
+                _ => continue,
+            };
+            let point = Point::from((f32::from_bits(corner.0), f32::from_bits(corner.1)));
+            let other = |i: usize| {
+                let [start, end] = walls[i].ends;
+                if key(start) == corner {
+                    end
+                } else {
+                    start
+                }
+            };
+            let (from, to) = (other(a), other(b));
+            if key(from) == key(to) {
+                continue;
+            }
+            let merged = Line::from_points(from, to);
+            let straight = walls[a]
+                .dropped
+                .iter()
+                .chain(&walls[b].dropped)
+                .chain([&point])
+                .all(|p| merged.distance_to_point(*p) <= tolerance);
+            // The corner also has to be between the other ends, or the walls
+            // double back on themselves
+            let between = distance(from, point) < distance(from, to) && distance(to, point) < distance(from, to);
+            if !straight || !between {
+                continue;
+            }
+
+            let mut dropped = std::mem::take(&mut walls[b].dropped);
+            dropped.push(point);
+            walls[a].dropped.extend(dropped);
+            walls[a].ends = [from, to];
+            walls[b].alive = false;
+            report.merged += 1;
+
+            at.remove(&corner);
+            for user in at.get_mut(&key(to)).unwrap() {
+                if *user == b {
+                    *user = a;
+                }
+            }
+        }
+
+        for wall in &walls {
+            if !wall.alive {
+                self.remove_wall(wall.id);
+                report.removed.push(wall.id);
+            } else if self.wall(wall.id).is_some_and(|line| [line.start(), line.end()] != wall.ends) {
+                self.set_wall(wall.id, wall.ends[0], wall.ends[1]);
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,129 @@
+//! Text drawn over the viewer, like the stats overlay. There is no font
+//! file to load, so it uses a tiny built in bitmap font.
+
+use sfml::graphics::{Color, PrimitiveType, RenderTarget, RenderWindow, Vertex, VertexArray, View};
+
+/// How big one pixel of the font is on screen
+const SCALE: f32 = 2.0;
+
+const GLYPH_WIDTH: f32 = 5.0;
+const GLYPH_HEIGHT: f32 = 7.0;
+const MARGIN: f32 = 8.0;
+
+/// 5x7 glyphs, one row per byte from the top, with the leftmost pixel in
+/// the highest bit. Letters are looked up in upper case, and anything not
+/// here is drawn as a space.
+const GLYPHS: &[(char, [u8; 7])] = &[
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('/', [0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('`', [0b01000, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+];
+
+/// Where on the window text goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    BottomLeft,
+}
+
+fn glyph(c: char) -> Option<&'static [u8; 7]> {
+    let c = c.to_ascii_uppercase();
+    GLYPHS.iter().find(|(g, _)| *g == c).map(|(_, rows)| rows)
+}
+
+fn quad(vertices: &mut VertexArray, x: f32, y: f32, width: f32, height: f32, colour: Color) {
+    for (dx, dy) in [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)] {
+        vertices.append(&Vertex::with_pos_color((x + dx, y + dy).into(), colour));
+    }
+}
+
+/// Draws `text` in a corner of the window on a dark box, with one line of
+/// the text per line on screen.
+pub fn draw_text(window: &mut RenderWindow, text: &str, colour: Color, corner: Corner) {
+    let advance = (GLYPH_WIDTH + 1.0) * SCALE;
+    let line_height = (GLYPH_HEIGHT + 3.0) * SCALE;
+    let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let rows = text.lines().count();
+    let size = window.size();
+
+    let box_height = rows as f32 * line_height + MARGIN;
+    let top = match corner {
+        Corner::TopLeft => 0.0,
+        Corner::BottomLeft => size.y as f32 - box_height - MARGIN,
+    };
+
+    let mut vertices = VertexArray::new(PrimitiveType::QUADS, 0);
+    quad(
+        &mut vertices,
+        MARGIN / 2.0,
+        top + MARGIN / 2.0,
+        columns as f32 * advance + MARGIN,
+        box_height,
+        Color::rgba(0, 0, 0, 180),
+    );
+    for (row, line) in text.lines().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let Some(rows) = glyph(c) else { continue };
+            let x = MARGIN + column as f32 * advance;
+            let y = top + MARGIN + row as f32 * line_height;
+            for (py, bits) in rows.iter().enumerate() {
+                for px in 0..5 {
+                    if bits & (1 << (4 - px)) != 0 {
+                        quad(&mut vertices, x + px as f32 * SCALE, y + py as f32 * SCALE, SCALE, SCALE, colour);
+                    }
+                }
+            }
+        }
+    }
+
+    // The text stays the same size wherever the camera is
+    window.set_view(&View::new(
+        (size.x as f32 / 2.0, size.y as f32 / 2.0).into(),
+        (size.x as f32, size.y as f32).into(),
+    ));
+    window.draw(&vertices);
+}
@@ -0,0 +1,533 @@
+//! A software renderer that draws the same picture as the viewer without
+//! needing a window or a GPU.
+
+use std::{fs::File, io, io::BufWriter, path::Path, time::Instant};
+
+use crate::{
+    bounce::{self, BounceSettings},
+    camera::Camera,
+    colour::Colour,
+    light::Light,
+    math::{Point, Scene},
+    random::Rng,
+    spectrum::{self, SpectralPath},
+    stats::Stats,
+};
+
+/// How far light reaches as a fraction of its radius. This has to match
+/// `RADIUS_MOD` in frag.frag.
+pub const RADIUS_MOD: f32 = 0.4;
+
+/// How bright a light is `distance` away from it, between 0 and 1. This is
+/// the same falloff that frag.frag uses.
+pub fn falloff(distance: f32, radius: f32) -> f32 {
+    (1.0 - distance / (radius * RADIUS_MOD)).clamp(0.0, 1.0)
+}
+
+/// A piece of the area a light reaches, where the light has one colour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LitTriangle {
+    pub points: [Point; 3],
+    /// How bright the light is at each point, from [`falloff`] and the cone
+    /// of the light
+    pub brightness: [f32; 3],
+    /// The colour of the light, tinted by the glass it went through
+    pub colour: Colour,
+}
+
+/// The area `light` reaches in `scene`, as triangles between neighbouring
+/// rays that are split wherever the rays go through glass.
+///
+/// Rays are cut off `reach` away from the light. Cutting them where the
+/// light runs out makes the falloff linear across every triangle, so
+/// fading the brightness between the corners looks the same as frag.frag,
+/// for when shaders are not available.
+pub fn light_triangles(scene: &Scene, light: &Light, reach: f32, stats: &mut Stats) -> Vec<LitTriangle> {
+    // The rays of a light with no radius have no direction either
+    if light.radius() <= 0.0 || light.rays().is_empty() {
+        return Vec::new();
+    }
+    let origin = light.position();
+    let rays = light
+        .rays()
+        .iter()
+        .map(|ray| {
+            let offset = ray.end() - origin;
+            let length = offset.x.hypot(offset.y);
+            let direction = Point::from((offset.x / length, offset.y / length));
+            (direction, light.cone_falloff(offset), ray.cast_through_glass(scene, stats))
+        })
+        .collect::<Vec<_>>();
+
+    let at = |direction: Point, distance: f32| origin + (direction.x * distance, direction.y * distance).into();
+    let brightness = |cone: f32, distance: f32| falloff(distance, light.radius()) * cone;
+
+    // Spotlights leave the gap between the edges of their cone dark
+    let wedges = if light.is_spot() { rays.len() - 1 } else { rays.len() };
+    let mut triangles = Vec::new();
+    for i in 0..wedges {
+        let (direction, cone, stretches) = &rays[i];
+        let (next_direction, next_cone, next_stretches) = &rays[(i + 1) % rays.len()];
+        let next_length = next_stretches.last().map_or(0.0, |(distance, _)| *distance).min(reach);
+
+        // Each stretch of this ray and the same distances along the next
+        // one make a quad, with the last one going out to the end of the
+        // next ray
+        let mut from = 0.0f32;
+        for (k, (to, tint)) in stretches.iter().enumerate() {
+            let last = k == stretches.len() - 1;
+            let (near, far) = (from.min(reach), to.min(reach));
+            from = *to;
+            if near >= far && !last {
+                continue;
+            }
+            let next_near = near.min(next_length);
+            let next_far = if last { next_length } else { far.min(next_length) };
+
+            let quad = [
+                (at(*direction, near), brightness(*cone, near)),
+                (at(*direction, far), brightness(*cone, far)),
+                (at(*next_direction, next_far), brightness(*next_cone, next_far)),
+                (at(*next_direction, next_near), brightness(*next_cone, next_near)),
+            ];
+            let colour = light.colour() * *tint;
+            let mut triangle = |[a, b, c]: [usize; 3]| {
+                triangles.push(LitTriangle {
+                    points: [quad[a].0, quad[b].0, quad[c].0],
+                    brightness: [quad[a].1, quad[b].1, quad[c].1],
+                    colour,
+                })
+            };
+            triangle([0, 1, 2]);
+            // Next to the light the quad is only a triangle
+            if near > 0.0 {
+                triangle([0, 2, 3]);
+            }
+        }
+    }
+    triangles
+}
+
+/// The light `light` sends through refractive walls in `scene`, split up
+/// by wavelength into triangles between neighbouring rays. They are meant
+/// to be added on top of everything else, and the colours of all the
+/// wavelengths together add up to the colour of the light.
+///
+/// The triangles start where the light first hits a refractive wall, as
+/// [`light_triangles`] already covers the way there.
+pub fn dispersed_triangles(scene: &Scene, light: &Light, stats: &mut Stats) -> Vec<LitTriangle> {
+    if light.radius() <= 0.0 || !spectrum::disperses(scene) {
+        return Vec::new();
+    }
+
+    // Only the rays that hit a refractive wall first are split up
+    let origin = light.position();
+    let rays = light
+        .rays()
+        .iter()
+        .map(|ray| {
+            let (_, hit) = ray.cast_past(scene, None, stats);
+            let material = scene.wall_material(hit?)?;
+            scene.material(material).refraction?;
+            let offset = ray.end() - origin;
+            let length = offset.x.hypot(offset.y);
+            Some((Point::from((offset.x / length, offset.y / length)), light.cone_falloff(offset)))
+        })
+        .collect::<Vec<_>>();
+    if rays.iter().all(Option::is_none) {
+        return Vec::new();
+    }
+
+    let reach = light.radius() * RADIUS_MOD;
+    let wedges = if light.is_spot() { rays.len() - 1 } else { rays.len() };
+    let mut triangles = Vec::new();
+    for (wavelength, weight) in spectrum::samples() {
+        let colour = light.colour() * weight;
+        let paths = rays
+            .iter()
+            .map(|ray| {
+                ray.map(|(direction, cone)| {
+                    (spectrum::trace(scene, origin, direction, wavelength, reach, stats), cone)
+                })
+            })
+            .collect::<Vec<_>>();
+        for i in 0..wedges {
+            if let (Some(path), Some(next)) = (&paths[i], &paths[(i + 1) % paths.len()]) {
+                fill_between(path, next, colour, light.radius(), &mut triangles);
+            }
+        }
+    }
+    triangles
+}
+
+/// Adds triangles covering the space between two neighbouring paths of
+/// the same wavelength, for as long as they bend at the same walls.
+fn fill_between(
+    (path, cone): &(SpectralPath, f32),
+    (next, next_cone): &(SpectralPath, f32),
+    colour: Colour,
+    radius: f32,
+    triangles: &mut Vec<LitTriangle>,
+) {
+    let segments = path.points.len().min(next.points.len()) - 1;
+    for k in 1..segments {
+        if path.walls.get(..k) != next.walls.get(..k) {
+            break;
+        }
+        let corner = |(point, distance): (Point, f32), cone: f32| (point, falloff(distance, radius) * cone);
+        let quad = [
+            corner(path.points[k], *cone),
+            corner(path.points[k + 1], *cone),
+            corner(next.points[k + 1], *next_cone),
+            corner(next.points[k], *next_cone),
+        ];
+        for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+            triangles.push(LitTriangle {
+                points: [quad[a].0, quad[b].0, quad[c].0],
+                brightness: [quad[a].1, quad[b].1, quad[c].1],
+                colour,
+            });
+        }
+    }
+}
+
+/// The light bouncing off walls from every one of `lights`, following
+/// `paths` random paths from each, as triangles to add on top of
+/// everything else.
+pub fn bounce_triangles(
+    scene: &Scene,
+    lights: &[Light],
+    bounces: usize,
+    paths: usize,
+    rng: &mut Rng,
+    stats: &mut Stats,
+) -> Vec<LitTriangle> {
+    let mut triangles = Vec::new();
+    for light in lights {
+        for bounced in bounce::bounce_lights(scene, light, bounces, paths, rng, stats) {
+            // Cut off where the light runs out, so the brightness can be
+            // faded across every triangle
+            triangles.extend(light_triangles(scene, &bounced, bounced.radius() * RADIUS_MOD, stats));
+        }
+    }
+    triangles
+}
+
+/// Calls `f` with the index of every pixel of a `width` by `height` image
+/// whose centre is inside the triangle, and how much each corner counts
+/// there, from 0 to 1.
+fn rasterise([a, b, c]: [Point; 3], (width, height): (u32, u32), mut f: impl FnMut(usize, [f32; 3])) {
+    let edge = |p: Point, q: Point, r: Point| (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+    let area = edge(a, b, c);
+    if area == 0.0 {
+        return;
+    }
+
+    let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+    let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+    let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as u32).min(width);
+    let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as u32).min(height);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = (x as f32 + 0.5, y as f32 + 0.5).into();
+            let w0 = edge(b, c, p) / area;
+            let w1 = edge(c, a, p) / area;
+            let w2 = edge(a, b, p) / area;
+            if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                f(y as usize * width as usize + x as usize, [w0, w1, w2]);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Colour>,
+}
+
+/// How many pixels there are in a canvas `width` by `height`, or `None`
+/// if that is too many to count.
+pub fn pixel_count(width: u32, height: u32) -> Option<usize> {
+    (width as usize).checked_mul(height as usize)
+}
+
+impl Canvas {
+    /// Panics if the canvas has more pixels than can be counted, which
+    /// [`pixel_count`] checks for.
+    pub fn new(width: u32, height: u32) -> Self {
+        let count = pixel_count(width, height).expect("the canvas has too many pixels");
+        Self {
+            width,
+            height,
+            pixels: vec![Colour::BLACK; count],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Colour {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn blend(&mut self, x: u32, y: u32, colour: Colour, alpha: f32) {
+        let index = self.index(x, y);
+        let pixel = &mut self.pixels[index];
+        *pixel = pixel.blend(colour, alpha);
+    }
+
+    /// Draws a one pixel wide line between two points in screen space.
+    pub fn draw_line(&mut self, start: Point, end: Point, colour: Colour) {
+        let steps = (end.x - start.x).abs().max((end.y - start.y).abs()).ceil().max(1.0);
+        for i in 0..=steps as u32 {
+            let t = i as f32 / steps;
+            let x = start.x + (end.x - start.x) * t;
+            let y = start.y + (end.y - start.y) * t;
+            if x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32 {
+                self.blend(x as u32, y as u32, colour, 1.0);
+            }
+        }
+    }
+
+    /// Sets every pixel whose centre is inside the triangle to `colour` in
+    /// `colours`, unless an earlier triangle already did.
+    fn cover_triangle(&self, corners: [Point; 3], colour: Colour, colours: &mut [Option<Colour>]) {
+        rasterise(corners, (self.width, self.height), |index, _| {
+            let pixel = &mut colours[index];
+            if pixel.is_none() {
+                *pixel = Some(colour);
+            }
+        });
+    }
+
+    /// Adds the colour of every triangle to the pixels it covers, fading
+    /// its brightness between the corners.
+    pub fn add_triangles(&mut self, triangles: &[LitTriangle], camera: &Camera) {
+        for triangle in triangles {
+            let points = triangle.points.map(|p| camera.world_to_screen(p));
+            self.add_triangle(points, triangle.brightness, triangle.colour);
+        }
+    }
+
+    /// Adds `colour` to every pixel whose centre is inside the triangle, as
+    /// bright as `brightness` at each corner and fading between them.
+    fn add_triangle(&mut self, corners: [Point; 3], brightness: [f32; 3], colour: Colour) {
+        rasterise(corners, (self.width, self.height), |index, [w0, w1, w2]| {
+            let alpha = w0 * brightness[0] + w1 * brightness[1] + w2 * brightness[2];
+            let pixel = &mut self.pixels[index];
+            *pixel = *pixel + colour * alpha;
+        });
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| p.to_rgb8()).collect()
+    }
+
+    /// Like [`Canvas::to_rgb8`], with every pixel fully opaque.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| {
+                let [r, g, b] = p.to_rgb8();
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    pub fn write_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb8())?;
+        Ok(())
+    }
+}
+
+/// Draws the scene like the viewer does: the walls, and then every light
+/// as a fan of its cast rays, fading out with [`falloff`], and last the
+/// rainbows from any prisms.
+pub fn render(scene: &Scene, lights: &[Light], camera: &Camera, width: u32, height: u32) -> Canvas {
+    render_with_stats(scene, lights, camera, width, height, &mut Stats::new())
+}
+
+/// Like [`render`], but counts the rays it cast and times drawing the walls,
+/// casting and filling in `stats`.
+pub fn render_with_stats(
+    scene: &Scene,
+    lights: &[Light],
+    camera: &Camera,
+    width: u32,
+    height: u32,
+    stats: &mut Stats,
+) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+
+    let start = Instant::now();
+    for (id, wall) in scene.wall_ids() {
+        let start = camera.world_to_screen(wall.start());
+        let end = camera.world_to_screen(wall.end());
+        let material = scene.wall_material(id).unwrap();
+        canvas.draw_line(start, end, scene.material(material).colour);
+    }
+    stats.record("walls", start.elapsed());
+
+    for light in lights {
+        draw_light(&mut canvas, scene, light, camera, stats);
+    }
+
+    // Light split up by prisms is added on top, as it can overlap itself
+    if spectrum::disperses(scene) {
+        let start = Instant::now();
+        for light in lights {
+            let triangles = dispersed_triangles(scene, light, stats);
+            canvas.add_triangles(&triangles, camera);
+        }
+        stats.record("disperse", start.elapsed());
+    }
+
+    canvas
+}
+
+/// Adds the light bouncing off walls to `canvas`, which should already
+/// have the scene drawn on it, following `settings.samples` paths from
+/// every light.
+pub fn draw_bounces(
+    canvas: &mut Canvas,
+    scene: &Scene,
+    lights: &[Light],
+    camera: &Camera,
+    settings: &BounceSettings,
+    rng: &mut Rng,
+    stats: &mut Stats,
+) {
+    let start = Instant::now();
+    let triangles = bounce_triangles(scene, lights, settings.bounces, settings.samples, rng, stats);
+    canvas.add_triangles(&triangles, camera);
+    stats.record("bounce", start.elapsed());
+}
+
+/// Averages canvases drawn with different random numbers, so that the
+/// noise in them evens out as more are added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    sum: Canvas,
+    /// The sum of the squared luminance of every pixel, for the noise
+    sum_squares: Vec<f32>,
+    count: u32,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            sum: Canvas::new(width, height),
+            sum_squares: vec![0.0; pixel_count(width, height).expect("the canvas has too many pixels")],
+            count: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.sum.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.sum.height
+    }
+
+    /// How many canvases have been added since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Adds a canvas, which has to be the same size.
+    pub fn add(&mut self, canvas: &Canvas) {
+        assert_eq!((canvas.width, canvas.height), (self.sum.width, self.sum.height));
+        let pixels = self.sum.pixels.iter_mut().zip(&mut self.sum_squares).zip(&canvas.pixels);
+        for ((sum, sum_squares), pixel) in pixels {
+            *sum = *sum + *pixel;
+            *sum_squares += pixel.luminance() * pixel.luminance();
+        }
+        self.count += 1;
+    }
+
+    /// Forgets everything added so far.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sum.width, self.sum.height);
+    }
+
+    /// The average of the canvases added so far.
+    pub fn average(&self) -> Canvas {
+        let scale = 1.0 / self.count.max(1) as f32;
+        Canvas {
+            pixels: self.sum.pixels.iter().map(|pixel| *pixel * scale).collect(),
+            ..self.sum.clone()
+        }
+    }
+
+    /// Roughly how far the brightness of the average is from where it would
+    /// end up with endless canvases: the standard error of the mean
+    /// luminance, averaged over the pixels that are lit at all. It halves
+    /// every time the count goes up four times, and needs at least two
+    /// canvases.
+    pub fn noise(&self) -> Option<f32> {
+        if self.count < 2 {
+            return None;
+        }
+        let n = self.count as f32;
+        let (mut total, mut lit) = (0.0, 0);
+        for (sum, sum_squares) in self.sum.pixels.iter().zip(&self.sum_squares) {
+            let mean = sum.luminance() / n;
+            if mean <= 0.0 {
+                continue;
+            }
+            let variance = ((sum_squares / n - mean * mean) * n / (n - 1.0)).max(0.0);
+            total += variance / n;
+            lit += 1;
+        }
+        Some(if lit == 0 { 0.0 } else { (total / lit as f32).sqrt() })
+    }
+}
+
+fn draw_light(canvas: &mut Canvas, scene: &Scene, light: &Light, camera: &Camera, stats: &mut Stats) {
+    let start = Instant::now();
+    let centre = camera.world_to_screen(light.position());
+    let triangles = light_triangles(scene, light, f32::INFINITY, stats);
+    stats.record("cast", start.elapsed());
+    let start = Instant::now();
+
+    // Every pixel is only lit once even where neighbouring triangles share
+    // an edge.
+    let mut colours = vec![None; canvas.pixels.len()];
+    for triangle in &triangles {
+        let points = triangle.points.map(|p| camera.world_to_screen(p));
+        canvas.cover_triangle(points, triangle.colour, &mut colours);
+    }
+
+    let radius = light.radius() * camera.zoom();
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let Some(colour) = colours[canvas.index(x, y)] else {
+                continue;
+            };
+
+            let offset = Point::from((x as f32 + 0.5 - centre.x, y as f32 + 0.5 - centre.y));
+            let alpha = falloff(offset.x.hypot(offset.y), radius) * light.cone_falloff(offset);
+            canvas.blend(x, y, colour, alpha);
+        }
+    }
+    stats.record("fill", start.elapsed());
+}
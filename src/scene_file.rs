@@ -0,0 +1,305 @@
+//! A small line based text format for scenes, so that they can be saved,
+//! loaded into the viewer and rendered headlessly.
+//!
+//! ```text
+//! # Comments start with a hash
+//! material <name> <r> <g> <b> [glass <r> <g> <b> | refract <a> <b>] [albedo <r> <g> <b>] [absorb <a>]
+//! wall <x1> <y1> <x2> <y2> [material]
+//! light <x> <y> <radius> [direction cone] [colour <r> <g> <b>]
+//! key <time> wall <n> <dx> <dy> <degrees> [easing]
+//! key <time> light <n> position <x> <y> [easing]
+//! key <time> light <n> radius <radius> [easing]
+//! key <time> light <n> direction <degrees> [easing]
+//! ```
+//!
+//! Walls and lights are numbered from zero in the order they appear, and
+//! keyframes refer to them by that number. The easing is one of `linear`
+//! (the default), `ease-in`, `ease-out`, `ease-in-out` or `step`. Colour
+//! channels go from 0 to 1, and materials have to be listed before the
+//! walls that use them. Walls of a `glass` material let light through,
+//! multiplied by the glass colour. Walls of a `refract` material bend light
+//! like a prism, with a refractive index of `a + b / λ²` for a wavelength
+//! of `λ` micrometres (`1.728 0.01342` is flint glass), which has to be at
+//! least 1 for every colour. The `albedo` is how much of each colour
+//! bounces off the walls when bounces are drawn, and is 0.5 otherwise.
+//! `absorb` is how much of the sound hitting the walls they soak up, from 0
+//! to 1, when tracing sound with [`crate::acoustics`], and is 0.1
+//! otherwise. A light with a direction and cone, both in degrees, is a
+//! spotlight.
+//!
+//! Files ending in `.svg`, `.png`, `.tmx` or `.tmj` are imported with the
+//! matching module in [`crate::import`] instead, giving a scene with only
+//! walls in it.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::{
+    colour::Colour,
+    import::{bitmap, svg, tiled},
+    light::Light,
+    material::Material,
+    math::{Point, Scene, Transform, WallId},
+    spectrum::Cauchy,
+    timeline::{Easing, Keyframe, LightTracks, Timeline},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Svg(svg::Error),
+    Bitmap(bitmap::Error),
+    Tiled(tiled::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Error::Svg(e) => write!(f, "{}", e),
+            Error::Bitmap(e) => write!(f, "{}", e),
+            Error::Tiled(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<svg::Error> for Error {
+    fn from(e: svg::Error) -> Self {
+        Error::Svg(e)
+    }
+}
+
+impl From<bitmap::Error> for Error {
+    fn from(e: bitmap::Error) -> Self {
+        Error::Bitmap(e)
+    }
+}
+
+impl From<tiled::Error> for Error {
+    fn from(e: tiled::Error) -> Self {
+        Error::Tiled(e)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SceneFile {
+    pub scene: Scene,
+    /// The walls in the order they were listed in the file
+    pub walls: Vec<WallId>,
+    pub lights: Vec<Light>,
+    pub timeline: Timeline,
+}
+
+impl SceneFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<SceneFile, Error> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("svg") => Ok(SceneFile::from(svg::load(path, &Default::default())?)),
+            Some("png") => Ok(SceneFile::from(bitmap::load(path, &Default::default())?)),
+            Some("tmx" | "tmj") => Ok(SceneFile::from(tiled::load(path, &Default::default())?)),
+            _ => Self::parse(&fs::read_to_string(path)?),
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<SceneFile, Error> {
+        let mut file = SceneFile::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            file.parse_line(&words).map_err(|message| Error::Parse {
+                line: number + 1,
+                message,
+            })?;
+        }
+
+        Ok(file)
+    }
+
+    fn parse_line(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["material", name, r, g, b, rest @ ..] => {
+                if self.scene.material_by_name(name).is_some() {
+                    return Err(format!("there is already a material called `{}`", name));
+                }
+                let drawn = colour(r, g, b)?;
+                let (rest, absorption) = match rest {
+                    [rest @ .., "absorb", a] => match number(a)? {
+                        a if (0.0..=1.0).contains(&a) => (rest, Some(a)),
+                        _ => return Err(format!("absorb has to be from 0 to 1, not `{}`", a)),
+                    },
+                    _ => (rest, None),
+                };
+                let (rest, albedo) = match rest {
+                    [rest @ .., "albedo", r, g, b] => (rest, Some(colour(r, g, b)?)),
+                    _ => (rest, None),
+                };
+                let mut material = match rest {
+                    [] => Material::new(*name, drawn),
+                    ["glass", r, g, b] => Material::glass(*name, drawn, colour(r, g, b)?),
+                    ["refract", a, b] => match Cauchy::new(number(a)?, number(b)?) {
+                        Some(refraction) => Material::refractive(*name, drawn, refraction),
+                        None => return Err(format!("`refract {} {}` bends light less than a vacuum does", a, b)),
+                    },
+                    _ => return Err(format!("unexpected `{}`", rest.join(" "))),
+                };
+                if let Some(albedo) = albedo {
+                    material.albedo = albedo;
+                }
+                if let Some(absorption) = absorption {
+                    material.absorption = absorption;
+                }
+                self.scene.add_material(material);
+            }
+            ["wall", x1, y1, x2, y2, material @ ..] => {
+                let start = point(x1, y1)?;
+                let end = point(x2, y2)?;
+                let material = match material {
+                    [] => None,
+                    [name] => Some(
+                        self.scene
+                            .material_by_name(name)
+                            .ok_or_else(|| format!("there is no material called `{}`", name))?,
+                    ),
+                    _ => return Err(format!("unexpected `{}`", material.join(" "))),
+                };
+
+                let wall = self.scene.add_line_no_graphics(start, end);
+                if let Some(material) = material {
+                    self.scene.set_wall_material(wall, material);
+                }
+                self.walls.push(wall);
+            }
+            ["light", x, y, radius, rest @ ..] => {
+                let (cone, colour) = match rest {
+                    ["colour", r, g, b] => (None, Some(colour(r, g, b)?)),
+                    [direction, cone, "colour", r, g, b] => (Some((direction, cone)), Some(colour(r, g, b)?)),
+                    [direction, cone] => (Some((direction, cone)), None),
+                    [] => (None, None),
+                    _ => return Err(format!("unexpected `{}`", rest.join(" "))),
+                };
+                let radius = match number(radius)? {
+                    radius if radius > 0.0 => radius,
+                    _ => return Err(format!("a light's radius has to be above 0, not `{}`", radius)),
+                };
+                let mut light = match cone {
+                    Some((direction, cone)) => Light::spot(
+                        point(x, y)?,
+                        radius,
+                        number(direction)?.to_radians(),
+                        match number(cone)? {
+                            cone if cone > 0.0 => cone.to_radians(),
+                            _ => return Err(format!("a light's cone has to be above 0, not `{}`", cone)),
+                        },
+                    ),
+                    None => Light::new(point(x, y)?, radius),
+                };
+                if let Some(colour) = colour {
+                    light.set_colour(colour);
+                }
+                self.lights.push(light);
+            }
+            ["key", time, "wall", n, dx, dy, degrees, rest @ ..] => {
+                let wall = *self
+                    .walls
+                    .get(index(n)?)
+                    .ok_or_else(|| format!("there is no wall {}", n))?;
+                let value = Transform {
+                    translation: point(dx, dy)?,
+                    rotation: number(degrees)?.to_radians(),
+                };
+                self.timeline.wall_track(wall).insert(Keyframe {
+                    time: number(time)?,
+                    value,
+                    easing: easing(rest)?,
+                });
+            }
+            ["key", time, "light", n, "position", x, y, rest @ ..] => {
+                let tracks = self.light_tracks(n)?;
+                tracks.position.insert(Keyframe {
+                    time: number(time)?,
+                    value: point(x, y)?,
+                    easing: easing(rest)?,
+                });
+            }
+            ["key", time, "light", n, "radius", radius, rest @ ..] => {
+                let tracks = self.light_tracks(n)?;
+                tracks.radius.insert(Keyframe {
+                    time: number(time)?,
+                    value: number(radius)?,
+                    easing: easing(rest)?,
+                });
+            }
+            ["key", time, "light", n, "direction", degrees, rest @ ..] => {
+                let tracks = self.light_tracks(n)?;
+                tracks.direction.insert(Keyframe {
+                    time: number(time)?,
+                    value: number(degrees)?.to_radians(),
+                    easing: easing(rest)?,
+                });
+            }
+            _ => return Err(format!("could not understand `{}`", words.join(" "))),
+        }
+        Ok(())
+    }
+
+    fn light_tracks(&mut self, n: &str) -> Result<&mut LightTracks, String> {
+        let light = index(n)?;
+        if light >= self.lights.len() {
+            return Err(format!("there is no light {}", n));
+        }
+        Ok(self.timeline.light_tracks(light))
+    }
+}
+
+impl From<Scene> for SceneFile {
+    fn from(scene: Scene) -> Self {
+        SceneFile {
+            walls: scene.wall_ids().map(|(id, _)| id).collect(),
+            scene,
+            ..Default::default()
+        }
+    }
+}
+
+fn number(word: &str) -> Result<f32, String> {
+    match word.parse::<f32>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!("`{}` is not a number", word)),
+    }
+}
+
+fn index(word: &str) -> Result<usize, String> {
+    word.parse()
+        .map_err(|_| format!("`{}` is not a wall or light number", word))
+}
+
+fn point(x: &str, y: &str) -> Result<Point, String> {
+    Ok((number(x)?, number(y)?).into())
+}
+
+fn colour(r: &str, g: &str, b: &str) -> Result<Colour, String> {
+    Ok(Colour::new(number(r)?, number(g)?, number(b)?))
+}
+
+fn easing(rest: &[&str]) -> Result<Easing, String> {
+    match rest {
+        [] => Ok(Easing::Linear),
+        [name] => Easing::from_name(name).ok_or_else(|| format!("unknown easing `{}`", name)),
+        _ => Err(format!("unexpected `{}`", rest.join(" "))),
+    }
+}
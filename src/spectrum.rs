@@ -0,0 +1,214 @@
+//! Light split up by wavelength, for walls that bend light like prisms do.
+//!
+//! Every wavelength bends by a different amount going into or out of a
+//! refractive wall, so white light going through a prism fans out into a
+//! rainbow. [`trace`] follows one wavelength through a scene, and
+//! [`crate::render::dispersed_triangles`] fills in between neighbouring
+//! rays of a light for every wavelength in [`samples`].
+
+use crate::{
+    colour::Colour,
+    math::{Line, Point, Scene, WallId},
+    material::MaterialId,
+    stats::Stats,
+};
+
+/// The shortest wavelength people can see, in nanometres
+pub const MIN_WAVELENGTH: f32 = 380.0;
+
+/// The longest wavelength people can see, in nanometres
+pub const MAX_WAVELENGTH: f32 = 700.0;
+
+/// How many wavelengths white light is split into
+pub const WAVELENGTH_SAMPLES: usize = 24;
+
+/// How many times light can bend before it is treated as stopped, so that
+/// light caught bouncing around inside a prism can not go on forever
+const MAX_BENDS: usize = 32;
+
+/// How much a material bends light of each wavelength, from Cauchy's
+/// equation: the refractive index is `a + b / λ²`, with `λ` in micrometres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cauchy {
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Cauchy {
+    /// Ordinary window glass, which hardly splits light up at all
+    pub const CROWN_GLASS: Cauchy = Cauchy { a: 1.5046, b: 0.0042 };
+    /// Heavy glass used for prisms, which splits light up a lot more
+    pub const FLINT_GLASS: Cauchy = Cauchy { a: 1.728, b: 0.01342 };
+
+    /// Gives `None` unless the refractive index is a number of at least 1
+    /// for every wavelength people can see, since light can not go faster
+    /// than it does in a vacuum.
+    pub fn new(a: f32, b: f32) -> Option<Self> {
+        Some(Self { a, b }).filter(Cauchy::is_valid)
+    }
+
+    /// Whether the refractive index is a number of at least 1 all the way
+    /// from [`MIN_WAVELENGTH`] to [`MAX_WAVELENGTH`]. It only goes one way
+    /// in between, so checking the ends is enough.
+    pub fn is_valid(&self) -> bool {
+        [MIN_WAVELENGTH, MAX_WAVELENGTH]
+            .into_iter()
+            .map(|wavelength| self.index(wavelength))
+            .all(|index| index.is_finite() && index >= 1.0)
+    }
+
+    /// The refractive index for light of `wavelength` nanometres.
+    pub fn index(&self, wavelength: f32) -> f32 {
+        let micrometres = wavelength / 1000.0;
+        self.a + self.b / (micrometres * micrometres)
+    }
+}
+
+/// Roughly what colour light of `wavelength` nanometres looks like, fading
+/// out towards the ends of what people can see.
+pub fn wavelength_colour(wavelength: f32) -> Colour {
+    let w = wavelength;
+    let (r, g, b) = if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&w) {
+        (0.0, 0.0, 0.0)
+    } else if w < 440.0 {
+        ((440.0 - w) / 60.0, 0.0, 1.0)
+    } else if w < 490.0 {
+        (0.0, (w - 440.0) / 50.0, 1.0)
+    } else if w < 510.0 {
+        (0.0, 1.0, (510.0 - w) / 20.0)
+    } else if w < 580.0 {
+        ((w - 510.0) / 70.0, 1.0, 0.0)
+    } else if w < 645.0 {
+        (1.0, (645.0 - w) / 65.0, 0.0)
+    } else {
+        (1.0, 0.0, 0.0)
+    };
+    let fade = if w < 420.0 {
+        0.3 + 0.7 * (w - MIN_WAVELENGTH) / 40.0
+    } else if w > 645.0 {
+        0.3 + 0.7 * (MAX_WAVELENGTH - w) / 55.0
+    } else {
+        1.0
+    };
+    Colour::new(r, g, b) * fade.max(0.0)
+}
+
+/// The wavelengths white light is split into, evenly spread across what
+/// people can see, each with how much of the light it carries. The
+/// colours add up to white.
+pub fn samples() -> Vec<(f32, Colour)> {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / WAVELENGTH_SAMPLES as f32;
+    let samples = (0..WAVELENGTH_SAMPLES)
+        .map(|i| {
+            let wavelength = MIN_WAVELENGTH + step * (i as f32 + 0.5);
+            (wavelength, wavelength_colour(wavelength))
+        })
+        .collect::<Vec<_>>();
+    let total = samples.iter().fold(Colour::BLACK, |total, (_, colour)| total + *colour);
+    samples
+        .into_iter()
+        .map(|(wavelength, c)| (wavelength, Colour::new(c.r / total.r, c.g / total.g, c.b / total.b)))
+        .collect()
+}
+
+/// Whether anything in `scene` bends light, so there is anything to split
+/// up by wavelength at all.
+pub fn disperses(scene: &Scene) -> bool {
+    scene.materials().any(|(_, material)| material.refraction.is_some())
+}
+
+/// Where one wavelength of light goes through a scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectralPath {
+    pub wavelength: f32,
+    /// Where the light starts, every point it bends at and where it stops,
+    /// with how far it has gone by each of them
+    pub points: Vec<(Point, f32)>,
+    /// The walls the light bent at, in order
+    pub walls: Vec<WallId>,
+}
+
+/// Follows light of `wavelength` nanometres from `start` in `direction`
+/// (which has to be one long) until it has gone `reach`, bending it at
+/// every refractive wall it goes into or out of. Light that can not get
+/// out of a wall at the angle it hits it is reflected instead, and any
+/// other wall stops it.
+pub fn trace(
+    scene: &Scene,
+    start: Point,
+    direction: Point,
+    wavelength: f32,
+    reach: f32,
+    stats: &mut Stats,
+) -> SpectralPath {
+    let mut path = SpectralPath {
+        wavelength,
+        points: vec![(start, 0.0)],
+        walls: Vec::new(),
+    };
+    let (mut from, mut direction, mut travelled) = (start, direction, 0.0f32);
+    // The refractive material the light is in, if it is not in the air
+    let mut inside: Option<MaterialId> = None;
+    let mut passed = None;
+    let index = |material: Option<MaterialId>| {
+        material
+            .and_then(|material| scene.material(material).refraction)
+            .map_or(1.0, |refraction| refraction.index(wavelength))
+    };
+
+    for _ in 0..MAX_BENDS {
+        let left = reach - travelled;
+        let to = from + (direction.x * left, direction.y * left).into();
+        let (cast, hit) = Line::from_points(from, to).cast_past(scene, passed, stats);
+        let end = cast.end();
+        travelled += (end.x - from.x).hypot(end.y - from.y);
+        path.points.push((end, travelled));
+
+        let Some(wall) = hit else { break };
+        let material = scene.wall_material(wall).unwrap();
+        if !scene.material(material).refraction.is_some_and(|refraction| refraction.is_valid()) {
+            break;
+        }
+
+        // Going out of the material the light is in, or into another one
+        let next = if inside == Some(material) { None } else { Some(material) };
+        // A wall with no length has no side to bend through
+        let Some(normal) = scene.wall(wall).unwrap().unit_normal() else { break };
+        match refract(direction, normal, index(inside), index(next)) {
+            Some(bent) => {
+                direction = bent;
+                inside = next;
+            }
+            None => direction = reflect(direction, normal),
+        }
+        path.walls.push(wall);
+        from = end;
+        passed = Some(wall);
+    }
+    path
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+/// Bends `direction` going through a surface with `normal` (which can face
+/// either way) from a refractive index of `from` to `to`, following Snell's
+/// law. Gives `None` if all of the light is reflected instead.
+fn refract(direction: Point, normal: Point, from: f32, to: f32) -> Option<Point> {
+    // Facing back towards where the light came from
+    let normal = if dot(direction, normal) > 0.0 { (-normal.x, -normal.y).into() } else { normal };
+    let ratio = from / to;
+    let cos_in = -dot(direction, normal);
+    let k = 1.0 - ratio * ratio * (1.0 - cos_in * cos_in);
+    if k < 0.0 {
+        return None;
+    }
+    let along_normal = ratio * cos_in - k.sqrt();
+    Some((direction.x * ratio + normal.x * along_normal, direction.y * ratio + normal.y * along_normal).into())
+}
+
+fn reflect(direction: Point, normal: Point) -> Point {
+    let d = 2.0 * dot(direction, normal);
+    (direction.x - normal.x * d, direction.y - normal.y * d).into()
+}
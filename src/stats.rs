@@ -0,0 +1,67 @@
+//! Counters for finding out where the time in a frame goes.
+
+use std::{fmt, time::Duration};
+
+/// What casting rays cost, added up over however many casts it was passed
+/// to. Timings are added with [`Stats::record`] by whoever is doing the
+/// work, since only they know where one phase ends and the next begins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub rays_cast: u64,
+    /// Walls a ray was checked against
+    pub intersection_tests: u64,
+    /// Intersection tests that shortened the ray
+    pub hits: u64,
+    /// Nodes of the scene's tree that were looked at
+    pub nodes_visited: u64,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `duration` to the time spent in `phase`.
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// The time spent in every phase, in the order they were first
+    /// recorded.
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    pub fn phase(&self, phase: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(name, _)| *name == phase)
+            .map(|(_, duration)| *duration)
+    }
+
+    /// Clears everything, ready for the next frame.
+    pub fn reset(&mut self) {
+        self.rays_cast = 0;
+        self.intersection_tests = 0;
+        self.hits = 0;
+        self.nodes_visited = 0;
+        self.phases.clear();
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rays cast: {}", self.rays_cast)?;
+        writeln!(f, "intersection tests: {}", self.intersection_tests)?;
+        writeln!(f, "hits: {}", self.hits)?;
+        write!(f, "nodes visited: {}", self.nodes_visited)?;
+        for (phase, duration) in &self.phases {
+            write!(f, "\n{}: {:.2} ms", phase, duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
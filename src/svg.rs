@@ -0,0 +1,297 @@
+//! Writing scenes out as standalone SVG files, for docs and design reviews.
+//! The output only depends on the scene, so it can be checked in and
+//! diffed.
+
+use std::fmt::Write;
+
+use crate::{
+    colour::Colour,
+    light::Light,
+    math::{Bounds, Point, Scene},
+    render::RADIUS_MOD,
+};
+
+/// What to draw for each light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightStyle {
+    /// Only a dot where the light is
+    Position,
+    /// Every ray of the light, cut short where it hits a wall
+    Rays,
+    /// The area the light can see, fading out like it does in the viewer
+    Visibility,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    pub light_style: LightStyle,
+    /// Spacing of a background grid in world units, if one should be drawn.
+    /// It is left out if it would need more than [`MAX_GRID_LINES`] lines.
+    pub grid: Option<f32>,
+    /// Whether to number the walls and lights
+    pub labels: bool,
+    /// Empty space around the scene, in world units
+    pub padding: f32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            light_style: LightStyle::Visibility,
+            grid: None,
+            labels: false,
+            padding: 20.0,
+        }
+    }
+}
+
+/// The most lines a background grid can have, across and down together
+pub const MAX_GRID_LINES: usize = 2000;
+
+/// Formats a number with at most two decimals and without trailing zeros,
+/// so the output stays small and stable.
+fn num(n: f32) -> String {
+    let s = format!("{:.2}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+fn hex(colour: Colour) -> String {
+    let [r, g, b] = colour.to_rgb8();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn points(list: impl IntoIterator<Item = Point>) -> String {
+    list.into_iter()
+        .map(|p| format!("{},{}", num(p.x), num(p.y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Scene {
+    /// The walls of the scene as an SVG document.
+    pub fn to_svg(&self) -> String {
+        self.to_svg_with(&[], &SvgOptions::default())
+    }
+
+    /// The scene and `lights` as an SVG document, drawn the way `options`
+    /// says.
+    pub fn to_svg_with(&self, lights: &[Light], options: &SvgOptions) -> String {
+        let bounds = self.svg_bounds(lights, options);
+        let width = bounds.max.x - bounds.min.x;
+        let height = bounds.max.y - bounds.min.y;
+
+        // Writing to a String can not fail, so the results are ignored
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+            num(bounds.min.x),
+            num(bounds.min.y),
+            num(width),
+            num(height),
+            num(width),
+            num(height)
+        );
+        let _ = writeln!(
+            svg,
+            r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="black"/>"#,
+            num(bounds.min.x),
+            num(bounds.min.y),
+            num(width),
+            num(height)
+        );
+
+        let grid_lines_fit = self.svg_grid_lines(lights, options) <= MAX_GRID_LINES;
+        if let Some(spacing) = options.grid.filter(|spacing| *spacing > 0.0 && grid_lines_fit) {
+            let _ = writeln!(svg, r##"  <g id="grid" stroke="#333333" stroke-width="0.5">"##);
+            for x in grid_lines(bounds.min.x, bounds.max.x, spacing) {
+                let _ = writeln!(
+                    svg,
+                    r#"    <line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                    num(x),
+                    num(bounds.min.y),
+                    num(x),
+                    num(bounds.max.y)
+                );
+            }
+            for y in grid_lines(bounds.min.y, bounds.max.y, spacing) {
+                let _ = writeln!(
+                    svg,
+                    r#"    <line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                    num(bounds.min.x),
+                    num(y),
+                    num(bounds.max.x),
+                    num(y)
+                );
+            }
+            let _ = writeln!(svg, "  </g>");
+        }
+
+        match options.light_style {
+            LightStyle::Position => {}
+            LightStyle::Rays => {
+                let _ = writeln!(svg, r#"  <g id="rays" stroke="white" stroke-width="0.5" stroke-opacity="0.5">"#);
+                for light in lights {
+                    for ray in light.rays() {
+                        let ray = ray.cast_in_scene(self);
+                        let _ = writeln!(
+                            svg,
+                            r#"    <line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                            num(ray.start().x),
+                            num(ray.start().y),
+                            num(ray.end().x),
+                            num(ray.end().y)
+                        );
+                    }
+                }
+                let _ = writeln!(svg, "  </g>");
+            }
+            LightStyle::Visibility => {
+                let _ = writeln!(svg, "  <defs>");
+                for (i, light) in lights.iter().enumerate() {
+                    let centre = light.position();
+                    let _ = writeln!(
+                        svg,
+                        r#"    <radialGradient id="light-{}" gradientUnits="userSpaceOnUse" cx="{}" cy="{}" r="{}">"#,
+                        i,
+                        num(centre.x),
+                        num(centre.y),
+                        num(light.radius() * RADIUS_MOD)
+                    );
+                    let colour = hex(light.colour());
+                    let _ = writeln!(svg, r#"      <stop offset="0" stop-color="{}" stop-opacity="1"/>"#, colour);
+                    let _ = writeln!(svg, r#"      <stop offset="1" stop-color="{}" stop-opacity="0"/>"#, colour);
+                    let _ = writeln!(svg, "    </radialGradient>");
+                }
+                let _ = writeln!(svg, "  </defs>");
+
+                let _ = writeln!(svg, r#"  <g id="visibility">"#);
+                for (i, light) in lights.iter().enumerate() {
+                    let polygon = self.light_visibility(light);
+                    let _ = writeln!(
+                        svg,
+                        r#"    <polygon points="{}" fill="url(#light-{})"/>"#,
+                        points(polygon.vertices),
+                        i
+                    );
+                }
+                let _ = writeln!(svg, "  </g>");
+            }
+        }
+
+        let _ = writeln!(svg, r#"  <g id="walls" stroke-width="2" stroke-linecap="round">"#);
+        for (id, wall) in self.wall_ids() {
+            let material = self.material(self.wall_material(id).unwrap());
+            let _ = writeln!(
+                svg,
+                r#"    <line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" data-material="{}"/>"#,
+                num(wall.start().x),
+                num(wall.start().y),
+                num(wall.end().x),
+                num(wall.end().y),
+                hex(material.colour),
+                escape(&material.name)
+            );
+        }
+        let _ = writeln!(svg, "  </g>");
+
+        let _ = writeln!(svg, r#"  <g id="lights" fill="yellow">"#);
+        for light in lights {
+            let centre = light.position();
+            let _ = writeln!(
+                svg,
+                r#"    <circle cx="{}" cy="{}" r="4"/>"#,
+                num(centre.x),
+                num(centre.y)
+            );
+        }
+        let _ = writeln!(svg, "  </g>");
+
+        if options.labels {
+            let _ = writeln!(svg, r#"  <g id="labels" fill="white" font-family="monospace" font-size="12">"#);
+            for (id, wall) in self.wall_ids() {
+                let middle = wall.midpoint();
+                let _ = writeln!(
+                    svg,
+                    r#"    <text x="{}" y="{}">wall {}</text>"#,
+                    num(middle.x + 4.0),
+                    num(middle.y - 4.0),
+                    id.number()
+                );
+            }
+            for (i, light) in lights.iter().enumerate() {
+                let centre = light.position();
+                let _ = writeln!(
+                    svg,
+                    r#"    <text x="{}" y="{}">light {}</text>"#,
+                    num(centre.x + 6.0),
+                    num(centre.y - 6.0),
+                    i
+                );
+            }
+            let _ = writeln!(svg, "  </g>");
+        }
+
+        let _ = writeln!(svg, "</svg>");
+        svg
+    }
+
+    /// How many lines the background grid asked for in `options` needs,
+    /// across and down together, which is 0 without one.
+    pub fn svg_grid_lines(&self, lights: &[Light], options: &SvgOptions) -> usize {
+        let Some(spacing) = options.grid.filter(|spacing| *spacing > 0.0) else {
+            return 0;
+        };
+        let bounds = self.svg_bounds(lights, options);
+        grid_line_count(bounds.min.x, bounds.max.x, spacing)
+            .saturating_add(grid_line_count(bounds.min.y, bounds.max.y, spacing))
+    }
+
+    /// The area the SVG shows: the walls and how far the lights reach, with
+    /// some padding.
+    fn svg_bounds(&self, lights: &[Light], options: &SvgOptions) -> Bounds {
+        let mut corners = self
+            .walls()
+            .flat_map(|wall| [wall.start(), wall.end()])
+            .collect::<Vec<_>>();
+        for light in lights {
+            let bounds = light.bounds();
+            corners.extend([bounds.min, bounds.max]);
+        }
+        Bounds::around(&corners)
+            .unwrap_or(Bounds {
+                min: (0.0, 0.0).into(),
+                max: (100.0, 100.0).into(),
+            })
+            .grow(options.padding)
+    }
+}
+
+/// How many lines of a grid with `spacing` fall between `min` and `max`.
+/// Spacings too small to count the lines of give `usize::MAX`.
+fn grid_line_count(min: f32, max: f32, spacing: f32) -> usize {
+    let first = (min as f64 / spacing as f64).ceil();
+    let last = (max as f64 / spacing as f64).floor();
+    (last - first + 1.0).max(0.0) as usize
+}
+
+/// Where the lines of a grid with `spacing` go between `min` and `max`.
+/// They are counted out rather than added up, as adding a small spacing to
+/// a big number can leave it where it was.
+fn grid_lines(min: f32, max: f32, spacing: f32) -> impl Iterator<Item = f32> {
+    let first = (min as f64 / spacing as f64).ceil();
+    (0..grid_line_count(min, max, spacing))
+        .map(move |i| ((first + i as f64) * spacing as f64) as f32)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
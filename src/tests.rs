@@ -1,4 +1,18 @@
+use crate::svg::*;
+use crate::import::svg as svg_import;
+use crate::import::bitmap::{trace, BitmapOptions, Mask};
+use crate::import::tiled::{self, TiledOptions};
+use crate::animation::*;
+use crate::colour::*;
+use crate::render::*;
+use crate::timeline::*;
+use crate::scene_file::*;
+use crate::world::*;
+use crate::camera::*;
 use crate::math::*;
+use crate::light::*;
+use crate::stats::*;
+use std::collections::HashSet;
 
 #[test]
 fn same_line_intersect() {
@@ -67,3 +81,1368 @@ fn test_scene_rtl() {
 
     assert_eq!(line_after_intersect, expected);
 }
+
+#[test]
+fn light_rays_do_not_drift() {
+    let mut light = Light::new((0.0, 0.0).into(), 500.0);
+
+    for i in 0..10_000 {
+        let x = (i % 1200) as f32 + 0.37;
+        let y = (i * 7 % 1200) as f32 - 0.61;
+        light.move_to((x, y).into());
+    }
+
+    light.move_to((321.5, 654.25).into());
+
+    let fresh = Light::new((321.5, 654.25).into(), 500.0);
+
+    assert_eq!(light.rays(), fresh.rays());
+}
+
+#[test]
+fn spotlights_only_shine_inside_their_cone() {
+    use std::f32::consts::FRAC_PI_2;
+
+    // Facing down the screen, 90 degrees wide
+    let light = SceneFile::parse("light 50 50 100 90 90").unwrap().lights.remove(0);
+    assert!(light.is_spot());
+    assert_eq!(light.rays().len(), 91);
+    for ray in light.rays() {
+        assert!(ray.end().y >= 50.0 - 1e-3, "{:?} leaves the cone", ray);
+    }
+
+    assert_eq!(light.cone_falloff((0.0, 10.0).into()), 1.0);
+    assert_eq!(light.cone_falloff((0.0, -10.0).into()), 0.0);
+    // Halfway across the soft edge
+    let angle = FRAC_PI_2 + FRAC_PI_2 / 2.0 - CONE_EDGE / 2.0;
+    assert!((light.cone_falloff((angle.cos(), angle.sin()).into()) - 0.5).abs() < 1e-3);
+
+    let scene = Scene::new();
+    let polygon = scene.light_visibility(&light);
+    assert_eq!(polygon.vertices[0], light.position());
+    assert!(polygon.vertices[1..].iter().all(|v| v.y >= 50.0 - 1e-3));
+
+    let lights = [light];
+    assert!(scene.illumination_at((50.0, 70.0).into(), &lights) > 0.0);
+    assert_eq!(scene.illumination_at((50.0, 30.0).into(), &lights), 0.0);
+
+    let canvas = render(&scene, &lights, &Camera::new(100, 100), 100, 100);
+    assert!(canvas.pixel(50, 55).r > 0.5);
+    assert_eq!(canvas.pixel(50, 45), Colour::BLACK);
+    assert_eq!(canvas.pixel(30, 55), Colour::BLACK);
+}
+
+#[test]
+fn cones_with_no_width_are_widened() {
+    assert!(SceneFile::parse("light 50 50 100 90 0").is_err());
+    let mut narrow = Light::new((50.0, 50.0).into(), 100.0);
+    narrow.set_cone(0.0);
+    assert_eq!(narrow.cone(), MIN_CONE);
+    assert_eq!(narrow.rays().len(), 2);
+    assert!(narrow.rays().iter().all(|ray| ray.end().x.is_finite() && ray.end().y.is_finite()));
+    assert_eq!(narrow.cone_falloff((1.0, 0.0).into()), 1.0);
+    assert_eq!(Light::spot((0.0, 0.0).into(), 10.0, 0.0, -1.0).cone(), MIN_CONE);
+}
+
+#[test]
+fn camera_round_trip() {
+    let mut camera = Camera::new(800, 600);
+    camera.pan(37.0, -12.5);
+    camera.zoom_at((100.0, 200.0).into(), 2.5);
+
+    let world = camera.screen_to_world((640.0, 480.0).into());
+    assert_eq!(camera.world_to_screen(world), (640.0, 480.0).into());
+}
+
+#[test]
+fn camera_zoom_keeps_cursor_fixed() {
+    let mut camera = Camera::new(800, 600);
+    let before = camera.screen_to_world((100.0, 200.0).into());
+
+    camera.zoom_at((100.0, 200.0).into(), 4.0);
+
+    assert_eq!(camera.screen_to_world((100.0, 200.0).into()), before);
+}
+
+#[test]
+fn camera_resize_does_not_stretch() {
+    let mut camera = Camera::new(800, 600);
+    let centre = camera.screen_to_world((400.0, 300.0).into());
+
+    camera.resize(1600, 600);
+
+    assert_eq!(camera.screen_to_world((800.0, 300.0).into()), centre);
+    assert_eq!(camera.zoom(), 1.0);
+}
+
+#[test]
+fn visibility_polygon_blocked_by_wall() {
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((10.0, -5.0).into(), (10.0, 5.0).into());
+
+    let polygon = scene.visibility_polygon((0.0, 0.0).into(), 100.0);
+
+    for vertex in &polygon.vertices {
+        if vertex.y.abs() < 4.0 && vertex.x > 0.0 {
+            assert!(vertex.x <= 10.0, "{:?} is behind the wall", vertex);
+        }
+    }
+    assert!(!polygon.vertices.contains(&(100.0, 0.0).into()));
+    assert_eq!(polygon.mesh().indices.len(), polygon.vertices.len() * 3);
+}
+
+#[test]
+fn illumination_is_blocked_by_walls_and_adds_up() {
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((10.0, -5.0).into(), (10.0, 5.0).into());
+    let lights = [Light::new((0.0, 0.0).into(), 100.0), Light::new((0.0, 20.0).into(), 100.0)];
+
+    assert!(!scene.is_visible((0.0, 0.0).into(), (20.0, 0.0).into()));
+    assert!(scene.is_visible((0.0, 0.0).into(), (20.0, 20.0).into()));
+    // Standing right against the wall still counts as seen
+    assert!(scene.is_visible((0.0, 0.0).into(), (10.0, 0.0).into()));
+    assert_eq!(
+        scene.visible_from((0.0, 0.0).into(), &[(5.0, 0.0).into(), (15.0, 0.0).into()]),
+        [true, false]
+    );
+
+    // Only the second light sees behind the wall
+    let behind = (20.0, 0.0).into();
+    let from_second = falloff(20.0f32.hypot(20.0), 100.0);
+    assert!((scene.illumination_at(behind, &lights) - from_second).abs() < 1e-6);
+
+    let both = scene.illumination_at_points(&[(0.0, 10.0).into(), (1000.0, 0.0).into()], &lights);
+    assert!((both[0] - 2.0 * falloff(10.0, 100.0)).abs() < 1e-6);
+    assert_eq!(both[1], 0.0);
+}
+
+#[test]
+fn lighting_world_only_recomputes_affected_lights() {
+    let mut world = LightingWorld::new();
+    let near = world.add_light(Light::new((0.0, 0.0).into(), 50.0));
+    let far = world.add_light(Light::new((1000.0, 0.0).into(), 50.0));
+    assert_eq!(world.update(), 2);
+
+    let box_points = vec![
+        (10.0, -5.0).into(),
+        (20.0, -5.0).into(),
+        (20.0, 5.0).into(),
+        (10.0, 5.0).into(),
+    ];
+    let occluder = world.add_occluder(Occluder::new(box_points, true));
+    assert!(world.is_dirty(near));
+    assert!(!world.is_dirty(far));
+    assert_eq!(world.update(), 1);
+
+    let shadowed = world
+        .visibility(near)
+        .unwrap()
+        .vertices
+        .iter()
+        .any(|p| p.y.abs() < 1.0 && (p.x - 10.0).abs() < 1e-3);
+    assert!(shadowed);
+
+    world.remove_occluder(occluder);
+    assert_eq!(world.update(), 1);
+    assert!(world.occluder(occluder).is_none());
+    assert_eq!(world.update(), 0);
+}
+
+#[test]
+fn moved_wall_blocks_at_new_position() {
+    let mut scene = Scene::new();
+    let wall = scene.add_line_no_graphics((10.0, -5.0).into(), (10.0, 5.0).into());
+
+    scene.translate_wall(wall, 10.0, 0.0);
+    let cast = Line::new(0.0, 0.0, 100.0, 0.0).cast_in_scene(&scene);
+    assert_eq!(cast.end(), (20.0, 0.0).into());
+
+    // A quarter turn around its middle leaves it lying along the ray
+    scene.rotate_wall(wall, std::f32::consts::FRAC_PI_2);
+    let moved = scene.wall(wall).unwrap();
+    assert!((moved.start().y - 0.0).abs() < 1e-4);
+    assert!((moved.bounds().min.x - 15.0).abs() < 1e-4);
+
+    scene.remove_wall(wall);
+    let cast = Line::new(0.0, 0.0, 100.0, 0.0).cast_in_scene(&scene);
+    assert_eq!(cast.end(), (100.0, 0.0).into());
+    assert!(scene.wall(wall).is_none());
+    assert_eq!(scene.remove_wall(wall), None);
+}
+
+#[test]
+fn removing_a_leaf_twice_leaves_the_free_list_alone() {
+    let mut tree = crate::bvh::Bvh::new();
+    let bounds = Line::new(0.0, 0.0, 10.0, 10.0).bounds();
+    let first = tree.insert(bounds, 1);
+    tree.insert(bounds, 2);
+    assert!(tree.remove(first));
+    assert!(!tree.remove(first));
+    assert!(!tree.remove(100));
+    let (third, fourth) = (tree.insert(bounds, 3), tree.insert(bounds, 4));
+    assert_ne!(third, fourth);
+    let mut items = Vec::new();
+    tree.visit(|_| true, |item| items.push(item));
+    items.sort();
+    assert_eq!(items, [2, 3, 4]);
+}
+
+#[test]
+fn refitted_tree_matches_brute_force() {
+    let mut seed = 12345u32;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 8) as f32 / (1 << 24) as f32 * 1000.0
+    };
+
+    let mut scene = Scene::new();
+    let walls = (0..200)
+        .map(|_| {
+            let start: Point = (random(), random()).into();
+            let end = start + (random() / 10.0 - 50.0, random() / 10.0 - 50.0).into();
+            scene.add_line_no_graphics(start, end)
+        })
+        .collect::<Vec<_>>();
+
+    for (i, wall) in walls.iter().enumerate() {
+        if i % 2 == 0 {
+            scene.translate_wall(*wall, random() / 20.0 - 25.0, random() / 20.0 - 25.0);
+            scene.rotate_wall(*wall, random() / 100.0);
+        }
+    }
+
+    for _ in 0..200 {
+        let ray = Line::new(random(), random(), random(), random());
+        let cast = ray.cast_in_scene(&scene);
+
+        let closest = scene
+            .walls()
+            .filter_map(|wall| ray.point_of_intersection(wall))
+            .map(|hit| (hit.x - ray.start().x).hypot(hit.y - ray.start().y))
+            .fold(f32::INFINITY, f32::min);
+        let travelled = (cast.end().x - ray.start().x).hypot(cast.end().y - ray.start().y);
+
+        if closest.is_finite() {
+            assert!((closest - travelled).abs() < 1e-2, "{} != {}", closest, travelled);
+        } else {
+            assert_eq!(cast, ray);
+        }
+    }
+}
+
+#[test]
+fn track_interpolates_with_easing() {
+    let mut track = Track::new();
+    track.insert(Keyframe { time: 2.0, value: 10.0, easing: Easing::Linear });
+    track.insert(Keyframe { time: 0.0, value: 0.0, easing: Easing::EaseIn });
+
+    assert_eq!(track.sample(-1.0), Some(0.0));
+    assert_eq!(track.sample(1.0), Some(2.5));
+    assert_eq!(track.sample(2.0), Some(10.0));
+    assert_eq!(track.sample(5.0), Some(10.0));
+    assert_eq!(Track::<f32>::new().sample(1.0), None);
+}
+
+#[test]
+fn timeline_evaluation_is_deterministic() {
+    let file = "
+        wall 100 0 100 50
+        light 0 0 300
+        key 0 wall 0 0 0 0
+        key 4 wall 0 50 0 90 ease-in-out
+        key 0 light 0 position 0 0
+        key 4 light 0 position 40 80 ease-out
+    ";
+    let mut played = SceneFile::parse(file).unwrap();
+    let mut direct = SceneFile::parse(file).unwrap();
+
+    let mut playback = Playback::new(played.timeline.duration());
+    for _ in 0..37 {
+        playback.advance(0.1);
+        played.timeline.evaluate(playback.time, &mut played.scene, &mut played.lights);
+    }
+    playback.scrub(-1.0);
+    played.timeline.evaluate(playback.time, &mut played.scene, &mut played.lights);
+
+    direct.timeline.evaluate(playback.time, &mut direct.scene, &mut direct.lights);
+
+    assert_eq!(played.lights, direct.lights);
+    assert_eq!(
+        played.scene.wall(played.walls[0]),
+        direct.scene.wall(direct.walls[0])
+    );
+}
+
+#[test]
+fn timeline_knows_which_lights_it_moves() {
+    let file = SceneFile::parse(
+        "
+        light 0 0 300
+        light 50 0 300
+        key 0 light 0 position 0 0
+        key 4 light 0 position 40 80
+        key 0 light 1 radius 100
+        ",
+    )
+    .unwrap();
+    assert!(file.timeline.moves_light(0));
+    assert!(!file.timeline.moves_light(1) && !file.timeline.moves_light(2));
+}
+
+#[test]
+fn scene_file_reports_bad_lines() {
+    assert!(SceneFile::parse(include_str!("../scenes/rotating_mirror.tracy")).is_ok());
+    assert!(SceneFile::parse(include_str!("../scenes/two_rooms.tracy")).is_ok());
+
+    match SceneFile::parse("wall 0 0 1 1\nkey 1 wall 3 0 0 0") {
+        Err(Error::Parse { line, .. }) => assert_eq!(line, 2),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+    assert!(SceneFile::parse("light 0 0 100\nkey 1 light 0 radius 5 bouncy").is_err());
+}
+
+#[test]
+fn headless_render_lights_around_the_light() {
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((60.0, 0.0).into(), (60.0, 100.0).into());
+    let lights = [Light::new((40.0, 50.0).into(), 100.0)];
+
+    let canvas = render(&scene, &lights, &Camera::new(100, 100), 100, 100);
+
+    // Bright next to the light, dark behind the wall and outside the falloff
+    assert!(canvas.pixel(42, 50).r > 0.8);
+    assert_eq!(canvas.pixel(80, 50), Colour::BLACK);
+    assert_eq!(canvas.pixel(40, 5), Colour::BLACK);
+    assert_eq!(canvas.pixel(60, 20), Colour::GREEN);
+}
+
+#[test]
+fn light_triangles_fade_like_the_shader() {
+    let light = Light::new((0.0, 0.0).into(), 100.0);
+    let reach = 100.0 * RADIUS_MOD;
+    let triangles = light_triangles(&Scene::new(), &light, reach, &mut Stats::new());
+    assert_eq!(triangles.len(), RAY_COUNT);
+
+    for triangle in &triangles {
+        for (point, brightness) in triangle.points.iter().zip(triangle.brightness) {
+            // Cut off where the light runs out, and as bright as the shader
+            // would make it at every corner
+            let distance = point.x.hypot(point.y);
+            assert!(distance <= reach + 1e-3);
+            assert!((brightness - falloff(distance, 100.0)).abs() < 1e-4);
+        }
+        assert_eq!(triangle.colour, Colour::WHITE);
+    }
+
+    // Nothing behind a spotlight
+    let spot = Light::spot((0.0, 0.0).into(), 100.0, 0.0, 1.0);
+    for triangle in light_triangles(&Scene::new(), &spot, reach, &mut Stats::new()) {
+        assert!(triangle.points.iter().all(|p| p.x >= -1e-3));
+    }
+}
+
+#[test]
+fn lights_with_no_radius_light_nothing() {
+    assert!(SceneFile::parse("light 10 10 0").is_err());
+    assert!(SceneFile::parse("light 10 10 -5 90 45").is_err());
+
+    // A radius keyframe can still shrink a light down to nothing
+    let light = Light::new((10.0, 10.0).into(), 0.0);
+    assert!(light_triangles(&Scene::new(), &light, 0.0, &mut Stats::new()).is_empty());
+    let canvas = render(&Scene::new(), &[light], &Camera::new(20, 20), 20, 20);
+    assert_eq!(canvas.pixel(10, 10), Colour::BLACK);
+}
+
+/// A pale yellow light at (40, 50) shining through ruby glass at x = 60
+/// onto a stone wall at x = 80.
+fn ruby_window() -> SceneFile {
+    SceneFile::parse(
+        "material ruby 1 0 0 glass 1 0.2 0.2
+         material stone 0.5 0.5 0.5
+         wall 60 20 60 80 ruby
+         wall 80 0 80 100 stone
+         light 40 50 200 colour 1 1 0.5",
+    )
+    .unwrap()
+}
+
+#[test]
+fn glass_tints_the_light_going_through_it() {
+    let file = ruby_window();
+    let (scene, lights) = (&file.scene, &file.lights);
+
+    let stretches = Line::new(40.0, 50.0, 100.0, 50.0).cast_through_glass(scene, &mut Stats::new());
+    assert_eq!(stretches.len(), 2);
+    assert!((stretches[0].0 - 20.0).abs() < 1e-4 && stretches[0].1 == Colour::WHITE);
+    assert!((stretches[1].0 - 40.0).abs() < 1e-4 && stretches[1].1 == Colour::new(1.0, 0.2, 0.2));
+
+    // Through the glass, but not the stone
+    let tinted = scene.colour_at((70.0, 50.0).into(), lights);
+    let brightness = falloff(30.0, 200.0);
+    assert!((tinted.r - brightness).abs() < 1e-4);
+    assert!((tinted.g - 0.2 * brightness).abs() < 1e-4);
+    assert!((tinted.b - 0.1 * brightness).abs() < 1e-4);
+    assert_eq!(scene.colour_at((90.0, 50.0).into(), lights), Colour::BLACK);
+
+    let canvas = render(scene, lights, &Camera::new(100, 100), 100, 100);
+    let before = canvas.pixel(55, 50);
+    let after = canvas.pixel(70, 50);
+    assert!(before.g > 0.5 && before.b < before.g);
+    assert!(after.r > 0.3 && after.g < after.r / 2.0);
+    assert_eq!(canvas.pixel(90, 50), Colour::BLACK);
+}
+
+#[test]
+fn illumination_counts_the_colour_of_the_light_and_the_glass() {
+    let file = ruby_window();
+    let (scene, lights) = (&file.scene, &file.lights);
+    let tinted = scene.colour_at((70.0, 50.0).into(), lights);
+    let illumination = scene.illumination_at((70.0, 50.0).into(), lights);
+    assert!((illumination - tinted.luminance()).abs() < 1e-4);
+
+    // A red light counts the same as white light through red glass
+    let mut red = Light::new((40.0, 50.0).into(), 200.0);
+    red.set_colour(Colour::new(1.0, 0.2, 0.1));
+    let untinted = scene.illumination_at((50.0, 50.0).into(), &[red]);
+    assert!((untinted - Colour::new(1.0, 0.2, 0.1).luminance() * falloff(10.0, 200.0)).abs() < 1e-4);
+}
+
+#[test]
+fn prisms_split_white_light_into_a_rainbow() {
+    use crate::material::Material;
+    use crate::spectrum::{self, Cauchy};
+
+    // Shorter wavelengths bend more, and all of them together are white
+    assert!(Cauchy::FLINT_GLASS.index(400.0) > Cauchy::FLINT_GLASS.index(700.0));
+    let white = spectrum::samples().iter().fold(Colour::BLACK, |total, (_, colour)| total + *colour);
+    for channel in [white.r, white.g, white.b] {
+        assert!((channel - 1.0).abs() < 1e-4);
+    }
+
+    // Light comes out of a flat slab going the same way it went in
+    let mut slab = Scene::new();
+    let glass = slab.add_material(Material::refractive("glass", Colour::WHITE, Cauchy::CROWN_GLASS));
+    for y in [0.0, 10.0] {
+        let wall = slab.add_line_no_graphics((-100.0, y).into(), (100.0, y).into());
+        slab.set_wall_material(wall, glass);
+    }
+    let path = spectrum::trace(&slab, (0.0, -10.0).into(), (0.6, 0.8).into(), 550.0, 100.0, &mut Stats::new());
+    assert_eq!(path.walls.len(), 2);
+    let (end, travelled) = path.points[3];
+    let (last, _) = path.points[2];
+    let out = end - last;
+    assert!((out.x / out.x.hypot(out.y) - 0.6).abs() < 1e-4);
+    assert!((travelled - 100.0).abs() < 1e-3);
+    // Bent towards the normal inside the glass
+    let inside = path.points[2].0 - path.points[1].0;
+    assert!(inside.x / inside.x.hypot(inside.y) < 0.6);
+
+    let file = SceneFile::parse(include_str!("../scenes/prism.tracy")).unwrap();
+    let light = &file.lights[0];
+    let direction = (light.direction().cos(), light.direction().sin()).into();
+    let angle_out = |wavelength| {
+        let path = spectrum::trace(&file.scene, light.position(), direction, wavelength, 1000.0, &mut Stats::new());
+        assert_eq!(path.walls.len(), 2);
+        let [.., (from, _), (to, _)] = path.points[..] else { unreachable!() };
+        (to.y - from.y).atan2(to.x - from.x)
+    };
+    assert!(angle_out(400.0) > angle_out(680.0) + 0.05);
+
+    // Both renderers get the same triangles, red on one side and violet on
+    // the other
+    let triangles = dispersed_triangles(&file.scene, light, &mut Stats::new());
+    assert!(!triangles.is_empty());
+    let canvas = render(&file.scene, &file.lights, &Camera::new(1200, 800), 1200, 800);
+    let canvas = &canvas;
+    let pixels = || (0..800).flat_map(move |y| (0..1200).map(move |x| canvas.pixel(x, y)));
+    assert!(pixels().any(|p| p.r > 0.1 && p.r > 4.0 * (p.g + p.b)));
+    assert!(pixels().any(|p| p.b > 0.1 && p.b > 4.0 * (p.r + p.g)));
+}
+
+#[test]
+fn refractive_indices_below_1_are_rejected() {
+    use crate::material::Material;
+    use crate::spectrum::{self, Cauchy};
+
+    assert_eq!(Cauchy::new(1.728, 0.01342), Some(Cauchy::FLINT_GLASS));
+    assert_eq!(Cauchy::new(0.0, 0.0), None);
+    assert_eq!(Cauchy::new(f32::MAX, f32::MAX), None);
+    assert!(SceneFile::parse("material p 1 1 1 refract 0 0").is_err());
+
+    // Made up without Cauchy::new, it just stops light like a wall
+    let mut scene = Scene::new();
+    let glass = scene.add_material(Material::refractive("glass", Colour::WHITE, Cauchy { a: 0.0, b: 0.0 }));
+    let wall = scene.add_line_no_graphics((-100.0, 0.0).into(), (100.0, 0.0).into());
+    scene.set_wall_material(wall, glass);
+    let path = spectrum::trace(&scene, (0.0, -10.0).into(), (0.6, 0.8).into(), 550.0, 100.0, &mut Stats::new());
+    assert!(path.walls.is_empty() && path.points.len() == 2);
+}
+
+#[test]
+fn refractive_walls_with_no_length_do_not_bend_light() {
+    let file = SceneFile::parse(
+        "material p 1 1 1 refract 1.5 0.01
+         wall 100 0 100 0 p
+         light 0 0 1000 0 10",
+    )
+    .unwrap();
+    let triangles = dispersed_triangles(&file.scene, &file.lights[0], &mut Stats::new());
+    assert!(triangles.iter().all(|triangle| triangle.points.iter().all(|p| p.x.is_finite() && p.y.is_finite())));
+}
+
+/// Two rooms 30 wide and 40 high side by side, with a doorway from y = 15
+/// to 25 in the wall between them at x = 30. The right one is made of
+/// `material`, and `rest` goes before the walls.
+fn two_rooms(rest: &str, material: &str) -> SceneFile {
+    SceneFile::parse(&format!(
+        "{rest}
+         wall 0 0 30 0
+         wall 30 40 0 40
+         wall 0 40 0 0
+         wall 30 0 30 15
+         wall 30 25 30 40
+         wall 30 0 60 0 {material}
+         wall 60 0 60 40 {material}
+         wall 60 40 30 40 {material}"
+    ))
+    .unwrap()
+}
+
+#[test]
+fn light_bounces_round_corners() {
+    use crate::bounce::{bounce_lights, BounceSettings};
+    use crate::random::Rng;
+
+    // Brick in the room the light can not see into
+    let file = two_rooms("material brick 0.8 0.3 0.2 albedo 0.8 0.3 0.2\nlight 20 35 200", "brick");
+    let (scene, lights) = (&file.scene, &file.lights);
+    let hidden = (45.0, 35.0).into();
+    assert_eq!(scene.illumination_at(hidden, lights), 0.0);
+
+    let bounced = bounce_lights(scene, &lights[0], 2, 64, &mut Rng::new(1), &mut Stats::new());
+    assert!(!bounced.is_empty() && bounced.len() <= 128);
+    assert!(bounced.iter().all(|light| light.is_spot() && light.colour().r.is_finite()));
+    assert_eq!(bounced, bounce_lights(scene, &lights[0], 2, 64, &mut Rng::new(1), &mut Stats::new()));
+    assert!(bounce_lights(scene, &lights[0], 0, 64, &mut Rng::new(1), &mut Stats::new()).is_empty());
+
+    let camera = Camera::new(60, 40);
+    let mut canvas = render(scene, lights, &camera, 60, 40);
+    assert_eq!(canvas.pixel(45, 35), Colour::BLACK);
+    let settings = BounceSettings { bounces: 2, samples: 64 };
+    draw_bounces(&mut canvas, scene, lights, &camera, &settings, &mut Rng::new(1), &mut Stats::new());
+    let lit = canvas.pixel(45, 35);
+    assert!(lit.r > 0.01 && lit.r > lit.b, "{:?} is not lit red by the brick", lit);
+
+    // Averaging
+    let mut accumulator = Accumulator::new(60, 40);
+    accumulator.add(&canvas);
+    accumulator.add(&Canvas::new(60, 40));
+    assert_eq!(accumulator.count(), 2);
+    assert_eq!(accumulator.average().pixel(45, 35), lit * 0.5);
+    accumulator.reset();
+    assert_eq!(accumulator.average().pixel(45, 35), Colour::BLACK);
+}
+
+#[test]
+fn light_does_not_bounce_off_walls_with_no_length() {
+    use crate::bounce::bounce_lights;
+    use crate::random::Rng;
+
+    // Far enough out that rounding lets every path hit the wall
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((1e6, 1e6).into(), (1e6, 1e6).into());
+    let light = Light::new((1e6 - 10.0, 1e6).into(), 100.0);
+    let bounced = bounce_lights(&scene, &light, 2, 64, &mut Rng::new(1), &mut Stats::new());
+    assert!(bounced.iter().all(|light| light.position().x.is_finite() && light.colour().r.is_finite()));
+}
+
+#[test]
+fn animation_renders_every_frame() {
+    let mut file = SceneFile::parse("light 10 10 50").unwrap();
+    let animation = Animation { frames: 5, fps: 10.0, width: 32, height: 32, bounces: None, spp: 1, seed: 0 };
+    script_light_path(&mut file.timeline, 0, &[(0.0, 0.0).into(), (32.0, 32.0).into()], 0.4);
+
+    let mut frames = Vec::new();
+    render_frames(&mut file.scene, &mut file.lights, &file.timeline, &animation, |n, canvas| {
+        frames.push((n, canvas));
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(frames.len(), 5);
+    assert_ne!(frames[0].1, frames[4].1);
+    assert_eq!(file.lights[0].position(), (32.0, 32.0).into());
+    assert_eq!(Output::from_path("out.GIF"), Output::Gif("out.GIF".into()));
+}
+
+#[test]
+fn one_png_only_takes_one_frame() {
+    let mut file = SceneFile::parse("light 10 10 50").unwrap();
+    let animation = Animation { frames: 5, fps: 10.0, width: 32, height: 32, bounces: None, spp: 1, seed: 0 };
+    assert_eq!(Output::from_path("out.png"), Output::Png("out.png".into()));
+    assert_eq!(Output::from_path("frames"), Output::Pngs("frames".into()));
+    let too_many = write_animation(&mut file.scene, &mut file.lights, &file.timeline, &animation, &Output::from_path("x.png"));
+    assert_eq!(too_many.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn pixel_counts_do_not_overflow() {
+    assert_eq!(pixel_count(1 << 10, 1 << 10), Some(1 << 20));
+    assert_eq!(pixel_count(u32::MAX, u32::MAX).and_then(|count| count.checked_mul(4)), None);
+}
+
+#[test]
+fn bounce_passes_converge_and_repeat_with_the_same_seed() {
+    use crate::bounce::BounceSettings;
+
+    let render = |spp, seed| {
+        let mut file = two_rooms("light 20 35 200", "");
+        let animation = Animation {
+            frames: 1,
+            fps: 10.0,
+            width: 60,
+            height: 40,
+            bounces: Some(BounceSettings { bounces: 2, samples: 4 }),
+            spp,
+            seed,
+        };
+        let mut frames = Vec::new();
+        let noise = render_frames(&mut file.scene, &mut file.lights, &file.timeline, &animation, |_, canvas| {
+            frames.push(canvas);
+            Ok(())
+        })
+        .unwrap();
+        (frames.pop().unwrap(), noise)
+    };
+
+    let (first, noise) = render(1, 7);
+    assert_eq!(noise, None);
+    assert_eq!(render(1, 7).0, first);
+    assert_ne!(render(1, 8).0, first);
+
+    // The noise goes down as more passes are averaged
+    let (_, few) = render(4, 7);
+    let (_, many) = render(32, 7);
+    let (few, many) = (few.unwrap(), many.unwrap());
+    assert!(many < few, "noise went from {} to {}", few, many);
+}
+
+#[test]
+fn sound_echoes_off_walls_into_an_impulse_response() {
+    use crate::acoustics::*;
+
+    // A wall 343 units (3.43 m) behind the receiver, which soaks up half
+    let file = SceneFile::parse(
+        "material curtain 1 1 1 absorb 0.5
+         wall 686 -1000 686 1000 curtain
+         light 0 0 100",
+    )
+    .unwrap();
+    assert_eq!(file.scene.material_by_name("curtain").map(|m| file.scene.material(m).absorption), Some(0.5));
+    assert!(SceneFile::parse("material foam 1 1 1 absorb 2").is_err());
+
+    let receiver = Receiver::new((343.0, 0.0).into(), 5.0);
+    let settings = AcousticSettings { rays: 4096, reflections: 4, ..Default::default() };
+    let responses = impulse_responses(&file.scene, (0.0, 0.0).into(), &[receiver], &settings, &mut Stats::new());
+    let arrivals = &responses[0].arrivals;
+
+    let direct = arrivals.iter().filter(|a| a.reflections == 0).collect::<Vec<_>>();
+    let echoes = arrivals.iter().filter(|a| a.reflections == 1).collect::<Vec<_>>();
+    assert!(!direct.is_empty() && !echoes.is_empty());
+    assert!(direct.iter().all(|a| (a.time - 0.01).abs() < 0.0002), "{:?}", direct);
+    assert!(echoes.iter().all(|a| (a.time - 0.03).abs() < 0.0002), "{:?}", echoes);
+    assert!(arrivals.windows(2).all(|pair| pair[0].time <= pair[1].time));
+    // Half of it is soaked up, and it has come three times as far
+    let energy = |arrivals: &[&Arrival]| arrivals.iter().map(|a| a.energy).sum::<f32>();
+    let ratio = energy(&echoes) / energy(&direct);
+    assert!((ratio - 0.5 / 3.0).abs() < 0.05, "echo is {} of the direct sound", ratio);
+
+    // At 250 Hz they arrive in the middle of samples 2 and 7
+    let samples = responses[0].samples(250, 0.05);
+    assert_eq!(samples.len(), 13);
+    assert!(samples[2] > samples[7] && samples[7] > 0.0 && samples[5] == 0.0);
+
+    let mut wav = Vec::new();
+    write_wav_to(&mut wav, &responses, 250, 0.05).unwrap();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(wav.len(), 44 + 13 * 2);
+    let sample = |n: usize| i16::from_le_bytes([wav[44 + 2 * n], wav[45 + 2 * n]]);
+    assert_eq!(sample(2), i16::MAX);
+    assert!(sample(7) > 0 && sample(5) == 0);
+}
+
+#[test]
+fn sound_stops_at_walls_with_no_length() {
+    use crate::acoustics::*;
+
+    let file = SceneFile::parse("wall 100 0 100 0\nlight 0 0 100").unwrap();
+    let settings = AcousticSettings { rays: 8, ..Default::default() };
+    let receivers = [Receiver::new((50.0, 0.0).into(), 5.0)];
+    let responses = impulse_responses(&file.scene, (0.0, 0.0).into(), &receivers, &settings, &mut Stats::new());
+    assert!(responses[0].arrivals.iter().all(|arrival| arrival.energy.is_finite()));
+}
+
+#[test]
+fn wav_files_too_big_for_their_header_are_rejected() {
+    use crate::acoustics::*;
+
+    let settings = AcousticSettings { rays: 8, ..Default::default() };
+    let receivers = [Receiver::new((50.0, 0.0).into(), 5.0)];
+    let responses = impulse_responses(&Scene::new(), (0.0, 0.0).into(), &receivers, &settings, &mut Stats::new());
+
+    // Far too long, which is found before making the samples
+    let error = write_wav_to(&mut Vec::new(), &responses, DEFAULT_SAMPLE_RATE, 1e9).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    // Too many bytes a second
+    let error = write_wav_to(&mut Vec::new(), &responses, u32::MAX, 0.0).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn paths_go_round_walls_and_through_doorways_that_are_wide_enough() {
+    let file = two_rooms("", "");
+    let scene = &file.scene;
+    let (from, to) = ((10.0, 20.0).into(), (50.0, 35.0).into());
+    let length = |path: &[Point]| path.windows(2).map(|p| (p[1].x - p[0].x).hypot(p[1].y - p[0].y)).sum::<f32>();
+
+    let graph = scene.line_of_sight_graph(2.0);
+    assert!(!graph.nodes.is_empty());
+    for (a, edges) in graph.edges.iter().enumerate() {
+        for &(b, distance) in edges {
+            assert!(graph.edges[b].iter().any(|&(back, d)| back == a && d == distance));
+        }
+    }
+
+    let path = graph.shortest_path(scene, from, to).unwrap();
+    assert_eq!((path[0], path[path.len() - 1]), (from, to));
+    assert!(path.len() >= 3, "{:?} goes straight through the wall", path);
+    assert!(path.windows(2).all(|p| graph.is_clear(scene, p[0], p[1])));
+    assert!(path.iter().all(|p| scene.walls().all(|wall| wall.distance_to_point(*p) >= 2.0)));
+    // Round the top of the doorway, two away from its corner
+    assert!(length(&path) < 50.0, "{:?} is {} long", path, length(&path));
+    assert_eq!(graph.shortest_path(scene, from, (20.0, 30.0).into()), Some(vec![from, (20.0, 30.0).into()]));
+
+    // Too big to get through the doorway, and not lost in the corners
+    let big = scene.line_of_sight_graph(6.0);
+    assert_eq!(big.shortest_path(scene, from, to), None);
+    assert!(big.nodes.iter().all(|p| scene.walls().all(|wall| wall.distance_to_point(*p) >= 6.0)));
+
+    // Round the end of a single wall
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((0.0, -50.0).into(), (0.0, 50.0).into());
+    let path = scene.line_of_sight_graph(5.0).shortest_path(&scene, (-20.0, 0.0).into(), (20.0, 0.0).into()).unwrap();
+    let around = 2.0 * 20.0f32.hypot(55.0);
+    assert!(length(&path) >= around && length(&path) < around + 10.0, "{:?}", path);
+}
+
+#[test]
+fn walls_inflate_by_a_radius_for_thick_rays() {
+    use crate::offset::*;
+
+    let near = |a: Point, b: Point| (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3;
+    let same = |got: Vec<Point>, want: &[(f32, f32)]| {
+        got.len() == want.len() && want.iter().all(|&w| got.iter().any(|&g| near(g, w.into())))
+    };
+    let square = [(0.0, 0.0).into(), (10.0, 0.0).into(), (10.0, 10.0).into(), (0.0, 10.0).into()];
+    let backwards = square.iter().rev().copied().collect::<Vec<Point>>();
+
+    // Either way round, out and in
+    for points in [&square[..], &backwards] {
+        let grown = offset_polygon(points, 2.0, Join::Miter);
+        assert!(same(grown, &[(-2.0, -2.0), (12.0, -2.0), (12.0, 12.0), (-2.0, 12.0)]));
+        let shrunk = offset_polygon(points, -2.0, Join::Round);
+        assert!(same(shrunk, &[(2.0, 2.0), (8.0, 2.0), (8.0, 8.0), (2.0, 8.0)]));
+    }
+    let rounded = offset_polygon(&square, 2.0, Join::Round);
+    assert!(rounded.len() > 4);
+    for p in rounded {
+        let from_square = (p.x.clamp(0.0, 10.0) - p.x).hypot(p.y.clamp(0.0, 10.0) - p.y);
+        assert!((1.999..2.1).contains(&from_square), "{:?} is {} out", p, from_square);
+    }
+
+    // A single wall, with square and round ends
+    let wall = [(0.0, 0.0).into(), (10.0, 0.0).into()];
+    let segment = Line::from_points(wall[0], wall[1]);
+    assert!(same(offset_polyline(&wall, 1.0, Join::Miter), &[(-1.0, 1.0), (-1.0, -1.0), (11.0, -1.0), (11.0, 1.0)]));
+    let capsule = offset_polyline(&wall, 1.0, Join::Round);
+    assert!(capsule.iter().all(|p| (0.999..1.1).contains(&segment.distance_to_point(*p))));
+    assert!(capsule.iter().any(|p| p.x < -0.9) && capsule.iter().any(|p| p.x > 10.9));
+
+    // A box, and a wall on its own
+    let file = SceneFile::parse(
+        "material stone 0.5 0.5 0.5
+         wall 0 0 10 0 stone
+         wall 10 0 10 10 stone
+         wall 10 10 0 10 stone
+         wall 0 10 0 0 stone
+         wall 30 0 30 10",
+    )
+    .unwrap();
+    let scene = &file.scene;
+    let stone = scene.material_by_name("stone").unwrap();
+    let inflated = scene.inflated(2.0, Join::Miter);
+    // Outside and inside the box, and round the wall
+    assert_eq!(inflated.wall_count(), 4 + 4 + 4);
+    assert_eq!(inflated.wall_ids().filter(|(id, _)| inflated.wall_material(*id) == Some(stone)).count(), 8);
+    assert_eq!(scene.inflated(6.0, Join::Miter).wall_count(), 4 + 4);
+
+    let mut stats = Stats::new();
+    let ray = Line::new(-20.0, 5.0, 50.0, 5.0);
+    assert!(near(ray.cast_in_scene(&inflated).end(), (-2.0, 5.0).into()));
+    let (cast, wall) = ray.cast_thick(scene, 2.0, &mut stats);
+    assert!(near(cast.end(), (-2.0, 5.0).into()));
+    assert_eq!(scene.wall(wall.unwrap()).map(|w| w.start()), Some((0.0, 10.0).into()));
+    assert!(stats.rays_cast == 1 && stats.hits >= 1);
+
+    // Going past the end of the wall on its own
+    let past = Line::new(20.0, 12.0, 40.0, 12.0);
+    assert_eq!(past.cast_thick(scene, 1.0, &mut stats), (past.clone(), None));
+    let (cast, _) = past.cast_thick(scene, 3.0, &mut stats);
+    assert!(near(cast.end(), (30.0 - 5.0f32.sqrt(), 12.0).into()), "{:?}", cast.end());
+    // Already touching
+    let (cast, wall) = Line::new(29.0, 5.0, 20.0, 5.0).cast_thick(scene, 2.0, &mut stats);
+    assert!(wall.is_some() && near(cast.end(), (29.0, 5.0).into()));
+}
+
+#[test]
+fn svg_export_colours_walls_by_material() {
+    let file = SceneFile::parse(
+        "
+        material glass 0.5 0.75 1
+        wall 0 0 100 0 glass
+        wall 0 0 0 100
+        light 50 50 200
+        ",
+    )
+    .unwrap();
+
+    let options = SvgOptions { labels: true, grid: Some(50.0), ..Default::default() };
+    let svg = file.scene.to_svg_with(&file.lights, &options);
+
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains(r##"<line x1="0" y1="0" x2="100" y2="0" stroke="#80bfff" data-material="glass"/>"##));
+    assert!(svg.contains(r##"stroke="#00ff00" data-material="wall""##));
+    assert!(svg.contains("<polygon points="));
+    assert!(svg.contains(">light 0</text>"));
+    assert_eq!(svg, file.scene.to_svg_with(&file.lights, &options));
+
+    assert!(SceneFile::parse("wall 0 0 1 1 glass").is_err());
+}
+
+#[test]
+fn svg_grid_stops_when_the_spacing_is_too_small_to_add_up() {
+    let scene = SceneFile::parse("wall 1000000 0 1000100 0").unwrap().scene;
+
+    let tiny = SvgOptions { grid: Some(0.001), ..Default::default() };
+    assert!(scene.svg_grid_lines(&[], &tiny) > MAX_GRID_LINES);
+    assert!(!scene.to_svg_with(&[], &tiny).contains(r#"id="grid""#));
+
+    let fine = SvgOptions { grid: Some(10.0), ..Default::default() };
+    let svg = scene.to_svg_with(&[], &fine);
+    assert_eq!(svg.matches("<line").count() - 1, scene.svg_grid_lines(&[], &fine));
+}
+
+#[test]
+fn svg_labels_keep_wall_numbers_after_a_removal() {
+    let mut scene = SceneFile::parse("wall 0 0 10 0\nwall 0 10 10 10\nwall 0 20 10 20").unwrap().scene;
+    let first = scene.wall_ids().next().unwrap().0;
+    scene.remove_wall(first);
+
+    let svg = scene.to_svg_with(&[], &SvgOptions { labels: true, ..Default::default() });
+    assert!(!svg.contains(">wall 0</text>"));
+    assert!(svg.contains(">wall 1</text>") && svg.contains(">wall 2</text>"));
+}
+
+#[test]
+fn svg_import_flattens_shapes_into_walls() {
+    let source = r##"
+        <svg xmlns="http://www.w3.org/2000/svg">
+            <defs><rect width="10" height="10"/></defs>
+            <g transform="translate(100 0)" stroke="#ff0000">
+                <rect x="0" y="0" width="50" height="20"/>
+                <line x1="0" y1="50" x2="0" y2="50"/>
+            </g>
+            <polyline points="0,0 10,0 10,10" style="stroke: blue"/>
+            <circle cx="0" cy="0" r="100"/>
+            <path d="M 0 200 C 0 300 100 300 100 200 Z"/>
+        </svg>
+    "##;
+    let options = svg_import::ImportOptions { tolerance: 0.5 };
+    let scene = svg_import::parse(source, &options).unwrap();
+
+    let red = scene.material_by_name("#ff0000").unwrap();
+    let rect = scene
+        .wall_ids()
+        .filter(|(id, _)| scene.wall_material(*id) == Some(red))
+        .map(|(_, wall)| wall.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(rect.len(), 4);
+    assert!(rect.iter().any(|wall| wall.start() == (100.0, 0.0).into() && wall.end() == (150.0, 0.0).into()
+        || wall.end() == (100.0, 0.0).into() && wall.start() == (150.0, 0.0).into()));
+    assert!(scene.material_by_name("#0000ff").is_some());
+
+    // Every flattened point of the circle is on it, and the walls between
+    // them stay within the tolerance of it
+    let circle = scene
+        .wall_ids()
+        .map(|(_, wall)| wall)
+        .filter(|wall| (wall.start().x.hypot(wall.start().y) - 100.0).abs() < 0.01 && wall.start().x < 100.0)
+        .collect::<Vec<_>>();
+    assert!(circle.len() >= 8);
+    for wall in circle {
+        let middle = wall.midpoint();
+        assert!(100.0 - middle.x.hypot(middle.y) <= 0.5);
+    }
+
+    // The curve and its closing line stay inside the curve's hull
+    for (_, wall) in scene.wall_ids() {
+        if wall.start().y >= 200.0 {
+            assert!(wall.start().y <= 275.0 && wall.end().y <= 275.0);
+        }
+    }
+
+    let error = svg_import::parse(r#"<svg><rect width="wide"/></svg>"#, &options).unwrap_err();
+    assert!(matches!(error, svg_import::Error::Attribute { line: 1, .. }), "{:?}", error);
+    assert!(svg_import::parse("<svg>", &options).is_err());
+}
+
+#[test]
+fn svg_import_converts_units_and_fits_view_boxes() {
+    let options = svg_import::ImportOptions { tolerance: 0.5 };
+    let only_wall = |source: &str| {
+        let scene = svg_import::parse(source, &options).unwrap();
+        let wall = scene.walls().next().unwrap().clone();
+        (wall.start(), wall.end())
+    };
+    assert_eq!(only_wall(r#"<svg><line x2="1in" y2="0.75pt"/></svg>"#), ((0.0, 0.0).into(), (96.0, 1.0).into()));
+    let stretched = r#"<svg viewBox="10 10 20 20" width="200" height="100" preserveAspectRatio="none">
+                           <line x1="10" y1="30" x2="30" y2="30"/>
+                       </svg>"#;
+    assert_eq!(only_wall(stretched), ((0.0, 100.0).into(), (200.0, 100.0).into()));
+    let centred = r#"<svg viewBox="0 0 10 10" width="200" height="100"><line x2="10"/></svg>"#;
+    assert_eq!(only_wall(centred), ((50.0, 0.0).into(), (150.0, 0.0).into()));
+}
+
+#[test]
+fn svg_import_rejects_units_it_can_not_convert() {
+    let options = svg_import::ImportOptions { tolerance: 0.5 };
+    for bad in [r#"<line x2="50%"/>"#, r#"<line x2="1e39"/>"#, r#"<line x2="2em"/>"#] {
+        let error = svg_import::parse(&format!("<svg>{}</svg>", bad), &options).unwrap_err();
+        assert!(matches!(error, svg_import::Error::Attribute { .. }), "{:?}", error);
+    }
+}
+
+#[test]
+fn bitmap_import_traces_closed_simplified_outlines() {
+    // A 10x10 square with a 4x4 hole in the middle, touching the border
+    let mask = Mask::from_fn(12, 12, |x, y| {
+        let square = x < 10 && y < 10;
+        let hole = (3..7).contains(&x) && (3..7).contains(&y);
+        if square && !hole { 1.0 } else { 0.0 }
+    });
+    let scene = trace(&mask, &BitmapOptions::default());
+
+    // Both outlines are closed, so every point starts one wall and ends
+    // another
+    let walls = scene.walls().cloned().collect::<Vec<_>>();
+    let key = |p: Point| ((p.x * 100.0).round() as i32, (p.y * 100.0).round() as i32);
+    let mut ends = walls.iter().flat_map(|wall| [key(wall.start()), key(wall.end())]).collect::<Vec<_>>();
+    ends.sort_unstable();
+    for pair in ends.chunks(2) {
+        assert_eq!(pair[0], pair[1]);
+    }
+    assert!(ends.windows(3).all(|w| w[0] != w[2]));
+
+    // Straight edges become single walls, with at most the corners cut
+    assert!((8..=16).contains(&walls.len()), "{} walls", walls.len());
+    for wall in &walls {
+        for p in [wall.start(), wall.end()] {
+            let outer = p.x.min(p.y).abs().min((10.0 - p.x).abs()).min((10.0 - p.y).abs());
+            let inner = (p.x - 3.0).abs().min((p.x - 7.0).abs()).min((p.y - 3.0).abs()).min((p.y - 7.0).abs());
+            assert!(outer.min(inner) <= 1.0, "{:?} is not on an outline", p);
+        }
+    }
+
+    let scaled = trace(&mask, &BitmapOptions { scale: 2.0, ..Default::default() });
+    let widest = scaled.walls().map(|wall| wall.end().x.max(wall.start().x)).fold(0.0, f32::max);
+    assert!((widest - 20.0).abs() <= 1.0);
+
+    let inverted = trace(&mask, &BitmapOptions { invert: true, ..Default::default() });
+    assert!(inverted.wall_count() > 0);
+}
+
+/// The walls of a scene as sorted endpoints, so scenes can be compared
+/// without caring about the order or direction of the walls.
+fn wall_set(scene: &Scene) -> Vec<[(i32, i32); 2]> {
+    let key = |p: Point| ((p.x * 100.0).round() as i32, (p.y * 100.0).round() as i32);
+    let mut walls = scene
+        .walls()
+        .map(|wall| {
+            let mut ends = [key(wall.start()), key(wall.end())];
+            ends.sort_unstable();
+            ends
+        })
+        .collect::<Vec<_>>();
+    walls.sort_unstable();
+    walls
+}
+
+#[test]
+fn tiled_maps_become_merged_walls() {
+    let tmx = r#"
+        <map orientation="orthogonal" width="4" height="3" tilewidth="16" tileheight="16">
+            <layer name="solid" width="4" height="3">
+                <data encoding="csv">
+                    1,1,1,0,
+                    1,0,0,0,
+                    2147483649,0,0,0
+                </data>
+            </layer>
+            <layer name="hidden" width="4" height="3" visible="0">
+                <data encoding="csv">0,0,0,1,0,0,0,1,0,0,0,1</data>
+            </layer>
+            <objectgroup name="occluders" offsetx="100">
+                <object id="1" x="0" y="100"><polygon points="0,0 10,0 0,10"/></object>
+                <object id="2" x="100" y="0" width="10" height="20" rotation="90"/>
+                <object id="3" x="5" y="5"><point/></object>
+            </objectgroup>
+        </map>
+    "#;
+    let scene = tiled::parse_tmx(tmx, &TiledOptions::default()).unwrap();
+    let walls = wall_set(&scene);
+
+    // The L of tiles only has its outline left, one wall per side
+    assert_eq!(walls.len(), 6 + 3 + 4);
+    assert!(walls.contains(&[(0, 0), (4800, 0)]));
+    assert!(walls.contains(&[(0, 0), (0, 4800)]));
+    // Rotated clockwise around the object's position
+    assert!(walls.contains(&[(18000, 1000), (20000, 1000)]));
+
+    // The same map as JSON, with the tiles zlib compressed
+    let gids = [1u32, 1, 1, 0, 1, 0, 0, 0, 0x8000_0001, 0, 0, 0];
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &gids.iter().flat_map(|gid| gid.to_le_bytes()).collect::<Vec<_>>()).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, compressed);
+
+    let tmj = format!(
+        r#"{{
+            "orientation": "orthogonal", "tilewidth": 16, "tileheight": 16,
+            "layers": [
+                {{ "type": "tilelayer", "name": "solid", "width": 4, "height": 3,
+                   "encoding": "base64", "compression": "zlib", "data": "{}" }},
+                {{ "type": "group", "name": "group", "layers": [
+                    {{ "type": "objectgroup", "name": "occluders", "offsetx": 100, "objects": [
+                        {{ "id": 1, "x": 0, "y": 100, "polygon": [{{ "x": 0, "y": 0 }}, {{ "x": 10, "y": 0 }}, {{ "x": 0, "y": 10 }}] }},
+                        {{ "id": 2, "x": 100, "y": 0, "width": 10, "height": 20, "rotation": 90 }},
+                        {{ "id": 3, "x": 5, "y": 5, "point": true }}
+                    ] }}
+                ] }}
+            ]
+        }}"#,
+        base64
+    );
+    assert_eq!(wall_set(&tiled::parse_tmj(&tmj, &TiledOptions::default()).unwrap()), walls);
+
+    let options = TiledOptions { layers: vec!["hidden".to_string()] };
+    assert_eq!(tiled::parse_tmx(tmx, &options).unwrap().wall_count(), 4);
+    assert!(tiled::parse_tmj(r#"{ "orientation": "isometric" }"#, &options).is_err());
+}
+
+#[test]
+fn tiled_json_nested_too_deep_does_not_blow_the_stack() {
+    assert!(tiled::parse_tmj(&"[".repeat(200_000), &TiledOptions::default()).is_err());
+}
+
+#[test]
+fn tiled_json_reads_surrogate_pairs_in_layer_names() {
+    let tmj = r#"{ "tilewidth": 16, "tileheight": 16, "layers": [
+        { "type": "tilelayer", "name": "\ud83e\uddf1", "width": 1, "height": 1, "data": [1] } ] }"#;
+    let options = TiledOptions { layers: vec!["🧱".to_string()] };
+    assert_eq!(tiled::parse_tmj(tmj, &options).unwrap().wall_count(), 4);
+}
+
+#[test]
+fn tiled_maps_with_numbers_out_of_range_are_rejected() {
+    let options = TiledOptions::default();
+    let tmx = |map: &str, object: &str| {
+        format!(
+            r#"<map {}><objectgroup name="o"><object {}/></objectgroup></map>"#,
+            map, object
+        )
+    };
+    let tiles = r#"tilewidth="16" tileheight="16""#;
+    assert_eq!(tiled::parse_tmx(&tmx(tiles, r#"width="10" height="10""#), &options).unwrap().wall_count(), 4);
+    for object in [r#"x="NaN""#, r#"x="inf""#, r#"y="-infinity""#, r#"x="1e39""#, r#"rotation="nan""#] {
+        let error = tiled::parse_tmx(&tmx(tiles, object), &options).unwrap_err();
+        assert!(matches!(error, tiled::Error::Map(_)), "{}: {:?}", object, error);
+    }
+    // Fits an f32 on its own, but not with the offset added
+    let error = tiled::parse_tmx(&tmx(tiles, r#"x="3e38" width="1e38""#), &options).unwrap_err();
+    assert!(matches!(error, tiled::Error::Map(_)), "{:?}", error);
+
+    for map in ["", r#"tilewidth="0" tileheight="16""#, r#"tilewidth="16" tileheight="1e-50""#] {
+        assert!(tiled::parse_tmx(&tmx(map, ""), &options).is_err(), "{}", map);
+    }
+    let tmj = r#"{ "tilewidth": 1e300, "tileheight": 16, "layers": [] }"#;
+    assert!(matches!(tiled::parse_tmj(tmj, &options), Err(tiled::Error::Map(_))));
+}
+
+#[test]
+fn optimize_cleans_up_clicked_walls() {
+    let mut scene = Scene::new();
+    // A chain of nearly straight walls, clicked one after another
+    let first = scene.add_line_no_graphics((0.0, 0.0).into(), (10.0, 0.1).into());
+    scene.add_line_no_graphics((10.2, 0.0).into(), (20.0, -0.1).into());
+    scene.add_line_no_graphics((20.0, -0.1).into(), (30.0, 0.0).into());
+    // A double click, and the same wall added twice backwards
+    scene.add_line_no_graphics((30.0, 0.0).into(), (30.0, 0.0).into());
+    let wall = scene.add_line_no_graphics((0.0, 0.0).into(), (0.0, 50.0).into());
+    let duplicate = scene.add_line_no_graphics((0.0, 50.0).into(), (0.0, 0.0).into());
+    // An animated wall stays where it is
+    let moved = scene.add_line_no_graphics((30.0, 0.0).into(), (40.0, 0.0).into());
+    scene.translate_wall(moved, 0.0, 5.0);
+
+    let report = scene.optimize(&HashSet::new());
+    assert_eq!(report.degenerate, 1);
+    assert_eq!(report.duplicates, 1);
+    assert_eq!(report.merged, 2);
+    assert_eq!(report.welded, 1);
+    assert_eq!(report.removed.len(), 4);
+    assert!(report.removed.contains(&duplicate));
+
+    assert_eq!(scene.wall_count(), 3);
+    let merged = scene.wall(first).unwrap();
+    assert_eq!(merged.start(), (0.0, 0.0).into());
+    assert!((merged.end().x - 30.0).abs() < 1e-4 && merged.end().y.abs() < 1e-4);
+    assert!(scene.wall(wall).is_some());
+    assert_eq!(scene.wall(moved).unwrap().start(), (30.0, 5.0).into());
+
+    // Casting is the same as before, apart from the tiny gap that got welded
+    let ray = Line::from_points((15.0, 20.0).into(), (15.0, -20.0).into());
+    assert!(ray.cast_in_scene(&scene).end().y.abs() < 0.2);
+
+    // A second pass has nothing left to do
+    assert_eq!(scene.optimize(&HashSet::new()), Default::default());
+}
+
+#[test]
+fn optimize_leaves_animated_walls_alone_while_they_are_at_rest() {
+    let mut file = SceneFile::parse(
+        "
+        wall 0 0 10 0
+        wall 10 0 20 0
+        key 0 wall 1 0 0 0
+        key 1 wall 1 0 50 0
+        ",
+    )
+    .unwrap();
+    let animated = file.timeline.animated_walls();
+    let moving = file.scene.wall_ids().nth(1).unwrap().0;
+    assert!(animated.contains(&moving));
+
+    // At time 0 the keyframes put wall 1 exactly where it rests
+    file.timeline.evaluate(0.0, &mut file.scene, &mut file.lights);
+    assert_eq!(file.scene.optimize(&animated).merged, 0);
+    assert_eq!(file.scene.wall_count(), 2);
+
+    file.timeline.evaluate(1.0, &mut file.scene, &mut file.lights);
+    assert_eq!(file.scene.wall(moving).unwrap().start(), (10.0, 50.0).into());
+}
+
+#[test]
+fn stats_count_the_work_of_casting() {
+    let mut scene = Scene::new();
+    scene.add_line_no_graphics((10.0, -10.0).into(), (10.0, 10.0).into());
+    scene.add_line_no_graphics((20.0, -10.0).into(), (20.0, 10.0).into());
+    scene.add_line_no_graphics((0.0, 100.0).into(), (10.0, 100.0).into());
+
+    let mut stats = Stats::new();
+    let ray = Line::from_points((0.0, 0.0).into(), (30.0, 0.0).into());
+    let cast = ray.cast_in_scene_with_stats(&scene, &mut stats);
+    assert_eq!(cast, ray.cast_in_scene(&scene));
+    assert_eq!(stats.rays_cast, 1);
+    assert!(stats.hits >= 1 && stats.hits <= 2);
+    assert!(stats.intersection_tests >= stats.hits && stats.intersection_tests <= 2);
+    assert!(stats.nodes_visited >= stats.intersection_tests);
+
+    // A ray that misses everything does not test walls it is nowhere near
+    let miss = Line::from_points((0.0, 50.0).into(), (30.0, 50.0).into());
+    let before = stats.clone();
+    miss.cast_in_scene_with_stats(&scene, &mut stats);
+    assert_eq!(stats.rays_cast, 2);
+    assert_eq!(stats.intersection_tests, before.intersection_tests);
+    assert_eq!(stats.hits, before.hits);
+
+    let light = Light::new((0.0, 0.0).into(), 100.0);
+    stats.reset();
+    render_with_stats(&scene, &[light], &Camera::new(40, 40), 40, 40, &mut stats);
+    assert_eq!(stats.rays_cast, RAY_COUNT as u64);
+    let phases = stats.phases().iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(phases, ["walls", "cast", "fill"]);
+    assert!(stats.to_string().contains("rays cast: 360"));
+}
+
+fn length(line: &Line) -> f32 {
+    (line.end().x - line.start().x).hypot(line.end().y - line.start().y)
+}
+
+/// Ends of lines somewhere in a typical scene.
+fn coordinate() -> impl proptest::strategy::Strategy<Value = f32> {
+    -1000.0f32..1000.0
+}
+
+fn any_line() -> impl proptest::strategy::Strategy<Value = Line> {
+    use proptest::prelude::*;
+    prop_oneof![
+        (coordinate(), coordinate(), coordinate(), coordinate()).prop_map(|(x1, y1, x2, y2)| Line::new(x1, y1, x2, y2)),
+        // Vertical, horizontal and zero length lines are rare otherwise
+        (coordinate(), coordinate(), coordinate()).prop_map(|(x, y1, y2)| Line::new(x, y1, x, y2)),
+        (coordinate(), coordinate(), coordinate()).prop_map(|(x1, x2, y)| Line::new(x1, y, x2, y)),
+        (coordinate(), coordinate()).prop_map(|(x, y)| Line::new(x, y, x, y)),
+    ]
+}
+
+proptest::proptest! {
+    #[test]
+    fn intersection_is_symmetric(a in any_line(), b in any_line()) {
+        let ab = a.point_of_intersection(&b);
+        let ba = b.point_of_intersection(&a);
+        proptest::prop_assert_eq!(ab.is_some(), ba.is_some(), "{:?} and {:?}", ab, ba);
+    }
+
+    #[test]
+    fn intersection_lies_on_both_lines(a in any_line(), b in any_line()) {
+        if let Some(hit) = a.point_of_intersection(&b) {
+            proptest::prop_assert!(a.distance_to_point(hit) < 0.01, "{:?} is not on {:?}", hit, a);
+            proptest::prop_assert!(b.distance_to_point(hit) < 0.01, "{:?} is not on {:?}", hit, b);
+        }
+    }
+
+    #[test]
+    fn cast_is_never_longer_than_the_ray(ray in any_line(), walls in proptest::collection::vec(any_line(), 0..20)) {
+        let mut scene = Scene::new();
+        for wall in &walls {
+            scene.add_line_no_graphics(wall.start(), wall.end());
+        }
+        let cast = ray.cast_in_scene(&scene);
+        proptest::prop_assert!(ray.distance_to_point(cast.start()) < 0.01);
+        proptest::prop_assert!(ray.distance_to_point(cast.end()) < 0.01);
+        proptest::prop_assert!(length(&cast) <= length(&ray) + 0.01);
+    }
+
+    #[test]
+    fn offset_round_trips(mut line in any_line(), x in coordinate(), y in coordinate()) {
+        let original = line.clone();
+        line.offset(x, y);
+        line.offset(-x, -y);
+        for (moved, start) in [(line.start(), original.start()), (line.end(), original.end())] {
+            proptest::prop_assert!((moved.x - start.x).abs() < 0.01 && (moved.y - start.y).abs() < 0.01, "{:?} became {:?}", original, line);
+        }
+    }
+}
+
+/// Two lines along the same infinite line, which random lines almost never
+/// are.
+fn colinear_lines() -> impl proptest::strategy::Strategy<Value = (Line, Line)> {
+    use proptest::prelude::*;
+    let direction = prop_oneof![
+        (-10i32..10, -10i32..10).prop_map(|(x, y)| (x as f32, y as f32)),
+        Just((0.0, 1.0)),
+        Just((1.0, 0.0)),
+    ];
+    (coordinate(), coordinate(), direction, -50i32..50, -50i32..50, -50i32..50, -50i32..50).prop_map(
+        |(x, y, (dx, dy), a, b, c, d)| {
+            let at = |t: i32| Point::from((x + dx * t as f32, y + dy * t as f32));
+            (Line::from_points(at(a), at(b)), Line::from_points(at(c), at(d)))
+        },
+    )
+}
+
+proptest::proptest! {
+    #[test]
+    fn colinear_lines_hit_where_they_first_overlap((a, b) in colinear_lines()) {
+        let (start, end) = (a.start(), a.end());
+        let along = |p: Point| ((p.x - start.x) * (end.x - start.x) + (p.y - start.y) * (end.y - start.y)) / length(&a).max(1.0);
+        let overlapping = a.distance_to_point(b.start()) < 0.01
+            || a.distance_to_point(b.end()) < 0.01
+            || b.distance_to_point(start) < 0.01;
+
+        match a.point_of_intersection(&b) {
+            Some(hit) => {
+                proptest::prop_assert!(overlapping);
+                proptest::prop_assert!(a.distance_to_point(hit) < 0.01 && b.distance_to_point(hit) < 0.01);
+                // Nothing on `b` comes before the hit, going along `a`
+                let first = if b.distance_to_point(start) < 0.01 { 0.0 } else { along(b.start()).min(along(b.end())) };
+                proptest::prop_assert!(along(hit) <= first.max(0.0) + 0.01, "{:?} {:?} hit at {:?}", a, b, hit);
+            }
+            None => proptest::prop_assert!(!overlapping, "{:?} and {:?} overlap", a, b),
+        }
+    }
+}
+
+// The tests from here on are bugs the property tests above turned up
+
+fn close_to(p: Point, x: f32, y: f32) -> bool {
+    (p.x - x).abs() < 0.01 && (p.y - y).abs() < 0.01
+}
+
+#[test]
+fn overlapping_vertical_lines_meet_where_the_first_one_starts_overlapping() {
+    // They met at the bottom of the overlap even when the first line goes
+    // down
+    let down = Line::new(0.0, 12.0, 0.0, 0.0);
+    let up = Line::new(0.0, 0.0, 0.0, 1.0);
+    assert_eq!(down.point_of_intersection(&up), Some((0.0, 1.0).into()));
+    assert_eq!(up.point_of_intersection(&down), Some((0.0, 0.0).into()));
+}
+
+#[test]
+fn steep_lines_keep_their_ends_when_moved_there_and_back() {
+    // The ends were worked out from the slope
+    let mut steep = Line::new(-297.94104, -500.0, -295.20908, 0.0);
+    steep.offset(-576.693, 0.0);
+    steep.offset(576.693, 0.0);
+    assert!(close_to(steep.start(), -297.94104, -500.0) && close_to(steep.end(), -295.20908, 0.0));
+}
+
+#[test]
+fn lines_on_the_same_line_meet_when_their_slopes_round_differently() {
+    let long = Line::new(25.0, 1024.3596, 0.0, 949.35956);
+    let short = Line::new(0.0, 949.35956, 1.0, 952.35956);
+    assert!(long.point_of_intersection(&short).is_some_and(|p| close_to(p, 1.0, 952.35956)));
+}
+
+#[test]
+fn hits_right_at_the_end_of_a_line_are_not_lost_to_rounding() {
+    // Otherwise rays get through where two walls meet
+    let point = Line::new(70.0, 10.0, 70.0, 10.0);
+    let through = Line::new(77.0, 11.0, -231.0, -33.0);
+    assert!(point.point_of_intersection(&through).is_some_and(|p| close_to(p, 70.0, 10.0)));
+    assert!(through.point_of_intersection(&point).is_some_and(|p| close_to(p, 70.0, 10.0)));
+    let first = Line::new(931.68146, 0.0, 1021.68146, 60.0);
+    let second = Line::new(1021.68146, 60.0, 1024.6814, 62.0);
+    assert!(first.point_of_intersection(&second).is_some_and(|p| close_to(p, 1021.68146, 60.0)));
+    assert!(second.point_of_intersection(&first).is_some_and(|p| close_to(p, 1021.68146, 60.0)));
+}
+
+#[test]
+fn steep_lines_hit_flat_lines_on_the_flat_line() {
+    // A steep line took y from its own slope, which put hits far off the
+    // flatter line
+    let steep = Line::new(358.0285, -611.03595, 357.22678, -924.10535);
+    let flat = Line::new(0.0, -853.7909, 413.76257, -853.7909);
+    let crossing = steep.point_of_intersection(&flat).unwrap();
+    assert_eq!(crossing.y, -853.7909);
+    assert!(steep.distance_to_point(crossing) < 0.01);
+}
+
+#[test]
+fn huge_and_nearly_vertical_lines_do_not_overflow_their_slope() {
+    // Those lines are vertical at the x of their start
+    let wide = Line::new(-3e38, 0.0, 3e38, 1.0);
+    assert_eq!((wide.start(), wide.end()), ((-3e38, 0.0).into(), (3e38, 1.0).into()));
+    let nearly_vertical = Line::new(0.0, -3e38, 1e-30, 3e38);
+    assert_eq!(nearly_vertical.end(), (0.0, 3e38).into());
+}
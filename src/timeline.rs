@@ -0,0 +1,258 @@
+use std::{collections::HashSet, f32::consts::PI};
+
+use crate::{
+    light::Light,
+    math::{Point, Scene, Transform, WallId},
+};
+
+/// How the value moves between one keyframe and the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// Holds the value of the keyframe until the next one
+    Step,
+}
+
+impl Easing {
+    /// Maps how far between two keyframes we are (0 to 1) to how far the
+    /// value should have moved.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => (1.0 - (t * PI).cos()) / 2.0,
+            Easing::Step => 0.0,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease-in" => Some(Easing::EaseIn),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" | "ease" => Some(Easing::EaseInOut),
+            "step" => Some(Easing::Step),
+            _ => None,
+        }
+    }
+}
+
+pub trait Interpolate: Copy {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+impl Interpolate for Point {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        (f32::interpolate(a.x, b.x, t), f32::interpolate(a.y, b.y, t)).into()
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        Transform {
+            translation: Point::interpolate(a.translation, b.translation, t),
+            rotation: f32::interpolate(a.rotation, b.rotation, t),
+        }
+    }
+}
+
+/// A value at a point in time. The easing is used on the way from this
+/// keyframe to the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    pub easing: Easing,
+}
+
+/// Keyframes for a single property, kept sorted by time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self { keys: Vec::new() }
+    }
+
+    /// Adds a keyframe, replacing any keyframe already at the same time.
+    pub fn insert(&mut self, key: Keyframe<T>) {
+        match self.keys.binary_search_by(|k| k.time.total_cmp(&key.time)) {
+            Ok(i) => self.keys[i] = key,
+            Err(i) => self.keys.insert(i, key),
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+
+    /// The value at `time`. Before the first keyframe and after the last
+    /// one the value is held.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let after = self.keys.partition_point(|key| key.time <= time);
+        match (after.checked_sub(1).map(|i| &self.keys[i]), self.keys.get(after)) {
+            (Some(from), Some(to)) => {
+                let t = (time - from.time) / (to.time - from.time);
+                Some(T::interpolate(from.value, to.value, from.easing.apply(t)))
+            }
+            (Some(from), None) => Some(from.value),
+            (None, Some(to)) => Some(to.value),
+            (None, None) => None,
+        }
+    }
+}
+
+impl<T: Interpolate> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The animated properties of one light.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LightTracks {
+    pub position: Track<Point>,
+    pub radius: Track<f32>,
+    /// In radians
+    pub direction: Track<f32>,
+}
+
+/// Keyframes for moving walls and lights over time. Evaluating only ever
+/// depends on the time asked for, so scrubbing backwards or rendering a
+/// single frame headlessly gives the same result as playing up to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    walls: Vec<(WallId, Track<Transform>)>,
+    lights: Vec<LightTracks>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.walls.is_empty() && self.lights.is_empty()
+    }
+
+    pub fn wall_track(&mut self, wall: WallId) -> &mut Track<Transform> {
+        let index = match self.walls.iter().position(|(id, _)| *id == wall) {
+            Some(index) => index,
+            None => {
+                self.walls.push((wall, Track::new()));
+                self.walls.len() - 1
+            }
+        };
+        &mut self.walls[index].1
+    }
+
+    /// The tracks of the light at `index` in the slice given to
+    /// [`Timeline::evaluate`].
+    pub fn light_tracks(&mut self, index: usize) -> &mut LightTracks {
+        if self.lights.len() <= index {
+            self.lights.resize_with(index + 1, LightTracks::default);
+        }
+        &mut self.lights[index]
+    }
+
+    /// Whether the light at `index` has keyframes moving it around.
+    pub fn moves_light(&self, index: usize) -> bool {
+        self.lights.get(index).is_some_and(|tracks| !tracks.position.keys().is_empty())
+    }
+
+    /// The walls that have keyframes moving them around.
+    pub fn animated_walls(&self) -> HashSet<WallId> {
+        self.walls.iter().map(|(id, _)| *id).collect()
+    }
+
+    pub fn duration(&self) -> f32 {
+        let walls = self.walls.iter().map(|(_, track)| track.duration());
+        let lights = self
+            .lights
+            .iter()
+            .flat_map(|tracks| [tracks.position.duration(), tracks.radius.duration(), tracks.direction.duration()]);
+        walls.chain(lights).fold(0.0, f32::max)
+    }
+
+    /// Puts every animated wall and light where it should be at `time`.
+    pub fn evaluate(&self, time: f32, scene: &mut Scene, lights: &mut [Light]) {
+        for (wall, track) in &self.walls {
+            if let Some(transform) = track.sample(time) {
+                scene.set_wall_transform(*wall, transform);
+            }
+        }
+
+        for (tracks, light) in self.lights.iter().zip(lights.iter_mut()) {
+            if let Some(radius) = tracks.radius.sample(time) {
+                light.set_radius(radius);
+            }
+            if let Some(position) = tracks.position.sample(time) {
+                light.move_to(position);
+            }
+            if let Some(direction) = tracks.direction.sample(time) {
+                light.set_direction(direction);
+            }
+        }
+    }
+}
+
+/// Play, pause and scrub state for showing a timeline in the viewer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    pub time: f32,
+    pub playing: bool,
+    pub looping: bool,
+    duration: f32,
+}
+
+impl Playback {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            time: 0.0,
+            playing: true,
+            looping: true,
+            duration,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.playing = !self.playing;
+    }
+
+    /// Moves the time forward by `dt` seconds if playing.
+    pub fn advance(&mut self, dt: f32) {
+        if self.playing {
+            self.seek(self.time + dt);
+        }
+    }
+
+    /// Jumps by `dt` seconds whether playing or not.
+    pub fn scrub(&mut self, dt: f32) {
+        self.seek(self.time + dt);
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = if self.looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        };
+    }
+}
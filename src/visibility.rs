@@ -0,0 +1,186 @@
+use std::f32::consts::PI;
+
+use crate::{
+    colour::Colour,
+    light::Light,
+    math::{Line, Point, Scene},
+    render::falloff,
+    stats::Stats,
+};
+
+/// Rays cast around the whole circle so the edge of the light is round
+/// even where there are no walls to aim at.
+const BOUNDARY_RAYS: usize = 64;
+
+/// How far short of its target a line of sight can stop and still count as
+/// getting there, so a wall passing right through the target does not hide
+/// it.
+const SIGHT_EPSILON: f32 = 1e-3;
+
+/// How far either side of a wall endpoint the extra rays are aimed, so
+/// that one ray stops on the corner and the other slips past it.
+const CORNER_EPSILON: f32 = 1e-4;
+
+/// The area a light can see, as a polygon that is star shaped around the
+/// light. The vertices are sorted by angle around the origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibilityPolygon {
+    pub origin: Point,
+    pub vertices: Vec<Point>,
+}
+
+/// A triangle fan ready to be uploaded to a GPU. The first vertex is the
+/// origin of the light.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl VisibilityPolygon {
+    pub fn triangles(&self) -> impl Iterator<Item = [Point; 3]> + '_ {
+        let count = self.vertices.len();
+        (0..count).map(move |i| {
+            [
+                self.origin,
+                self.vertices[i],
+                self.vertices[(i + 1) % count],
+            ]
+        })
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        let mut vertices = Vec::with_capacity(self.vertices.len() + 1);
+        vertices.push([self.origin.x, self.origin.y]);
+        vertices.extend(self.vertices.iter().map(|p| [p.x, p.y]));
+
+        let count = self.vertices.len() as u32;
+        let indices = (0..count)
+            .flat_map(|i| [0, i + 1, (i + 1) % count + 1])
+            .collect();
+
+        Mesh { vertices, indices }
+    }
+}
+
+impl Scene {
+    /// Works out what a light at `origin` reaching `radius` can see, by
+    /// casting towards every wall endpoint in range and around the circle.
+    pub fn visibility_polygon(&self, origin: Point, radius: f32) -> VisibilityPolygon {
+        self.visibility_cone(origin, radius, 0.0, 2.0 * PI)
+    }
+
+    /// Like [`Scene::visibility_polygon`], but only looking `cone` radians
+    /// wide around `direction`. Unless the cone is the whole circle, the
+    /// first vertex is `origin` itself so the polygon closes at the light.
+    pub fn visibility_cone(&self, origin: Point, radius: f32, direction: f32, cone: f32) -> VisibilityPolygon {
+        let whole = cone >= 2.0 * PI;
+        let start = if whole { 0.0 } else { direction - cone / 2.0 };
+
+        let mut angles = (0..BOUNDARY_RAYS)
+            .map(|i| i as f32 * 2.0 * PI / BOUNDARY_RAYS as f32)
+            .collect::<Vec<_>>();
+
+        for wall in self.walls() {
+            for corner in [wall.start(), wall.end()] {
+                let to_corner = corner - origin;
+                if to_corner.x.hypot(to_corner.y) > radius {
+                    continue;
+                }
+
+                let angle = to_corner.y.atan2(to_corner.x);
+                angles.extend([angle - CORNER_EPSILON, angle, angle + CORNER_EPSILON]);
+            }
+        }
+
+        // Measured from the start of the cone, so that sorting puts them in
+        // order across it
+        let mut angles = angles
+            .into_iter()
+            .map(|angle| (angle - start).rem_euclid(2.0 * PI))
+            .filter(|angle| whole || *angle <= cone)
+            .collect::<Vec<_>>();
+        if !whole {
+            angles.extend([0.0, cone]);
+        }
+        angles.sort_by(f32::total_cmp);
+        angles.dedup();
+
+        let mut vertices = if whole { Vec::new() } else { vec![origin] };
+        vertices.extend(angles.into_iter().map(|angle| {
+            let angle = start + angle;
+            let end = origin + (angle.cos() * radius, angle.sin() * radius).into();
+            Line::from_points(origin, end).cast_in_scene(self).end()
+        }));
+
+        VisibilityPolygon { origin, vertices }
+    }
+
+    /// What `light` can see, taking its cone into account.
+    pub fn light_visibility(&self, light: &Light) -> VisibilityPolygon {
+        self.visibility_cone(light.position(), light.radius(), light.direction(), light.cone())
+    }
+
+    /// Whether nothing in the scene is in the way between `a` and `b`.
+    pub fn is_visible(&self, a: Point, b: Point) -> bool {
+        if a == b {
+            return true;
+        }
+        let end = Line::from_points(a, b).cast_in_scene(self).end();
+        (end.x - b.x).hypot(end.y - b.y) <= SIGHT_EPSILON
+    }
+
+    /// [`Scene::is_visible`] from `a` to each of `points`.
+    pub fn visible_from(&self, a: Point, points: &[Point]) -> Vec<bool> {
+        points.iter().map(|p| self.is_visible(a, *p)).collect()
+    }
+
+    /// How brightly `lights` shine on `point`, using the same [`falloff`]
+    /// as the renderers. Every light that reaches the point adds its
+    /// brightness, so this goes over 1 where lights overlap. Each light
+    /// counts as much as the luminance of its colour, after any glass it
+    /// went through, so a white light counts fully and a red one less.
+    pub fn illumination_at(&self, point: Point, lights: &[Light]) -> f32 {
+        lights
+            .iter()
+            .filter_map(|light| self.light_reaching(light, point).map(|reaching| (light, reaching)))
+            .map(|(light, (brightness, tint))| brightness * (light.colour() * tint).luminance())
+            .sum()
+    }
+
+    /// Like [`Scene::illumination_at`], but adding up the colours of the
+    /// lights as well.
+    pub fn colour_at(&self, point: Point, lights: &[Light]) -> Colour {
+        lights
+            .iter()
+            .filter_map(|light| self.light_reaching(light, point).map(|(brightness, tint)| (light, brightness, tint)))
+            .fold(Colour::BLACK, |total, (light, brightness, tint)| {
+                total + light.colour() * tint * brightness
+            })
+    }
+
+    /// How bright `light` is at `point` and what glass on the way tints it
+    /// by, if it gets there.
+    fn light_reaching(&self, light: &Light, point: Point) -> Option<(f32, Colour)> {
+        let to_point = point - light.position();
+        let distance = to_point.x.hypot(to_point.y);
+        let brightness = falloff(distance, light.radius()) * light.cone_falloff(to_point);
+        // Casting is the expensive part, so it is skipped when the light
+        // would not reach anyway
+        if brightness <= 0.0 {
+            return None;
+        }
+        if distance == 0.0 {
+            return Some((brightness, Colour::WHITE));
+        }
+
+        let stretches = Line::from_points(light.position(), point).cast_through_glass(self, &mut Stats::new());
+        let (reached, tint) = *stretches.last()?;
+        (reached >= distance - SIGHT_EPSILON).then_some((brightness, tint))
+    }
+
+    /// [`Scene::illumination_at`] for each of `points`.
+    pub fn illumination_at_points(&self, points: &[Point], lights: &[Light]) -> Vec<f32> {
+        points.iter().map(|p| self.illumination_at(*p, lights)).collect()
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    arena::{Arena, Index},
+    light::Light,
+    math::{Bounds, Point, Scene, WallId},
+    visibility::VisibilityPolygon,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OccluderId(Index);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(Index);
+
+/// A chain of walls that blocks light. When `closed` is set the last
+/// point is joined back up to the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occluder {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+impl Occluder {
+    pub fn new(points: Vec<Point>, closed: bool) -> Self {
+        Self { points, closed }
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        let wrap = if self.closed && self.points.len() > 2 {
+            self.points.last().copied().zip(self.points.first().copied())
+        } else {
+            None
+        };
+        self.points
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .chain(wrap)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WorldOccluder {
+    occluder: Occluder,
+    walls: Vec<WallId>,
+}
+
+#[derive(Debug, Clone)]
+struct WorldLight {
+    light: Light,
+    visibility: VisibilityPolygon,
+    dirty: bool,
+}
+
+/// A set of occluders and lights for using tracy as the lighting engine of
+/// a game. Changes only mark the lights they can affect as dirty, and
+/// [`LightingWorld::update`] only recomputes those.
+#[derive(Debug)]
+pub struct LightingWorld {
+    occluders: Arena<WorldOccluder>,
+    lights: Arena<WorldLight>,
+    scene: Scene,
+}
+
+impl LightingWorld {
+    pub fn new() -> Self {
+        Self {
+            occluders: Arena::new(),
+            lights: Arena::new(),
+            scene: Scene::new(),
+        }
+    }
+
+    pub fn add_occluder(&mut self, occluder: Occluder) -> OccluderId {
+        self.mark_lights_near(&occluder);
+        let walls = occluder
+            .segments()
+            .map(|(start, end)| self.scene.add_line_no_graphics(start, end))
+            .collect();
+        OccluderId(self.occluders.insert(WorldOccluder { occluder, walls }))
+    }
+
+    /// Replaces the shape of an occluder. Walls are moved in place when the
+    /// number of segments stays the same, which is the common case for
+    /// occluders that are animated. Returns false if the handle no longer
+    /// points to anything.
+    pub fn update_occluder(&mut self, id: OccluderId, occluder: Occluder) -> bool {
+        let entry = match self.occluders.get_mut(id.0) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let segments = occluder.segments().collect::<Vec<_>>();
+        if segments.len() == entry.walls.len() {
+            for (wall, (start, end)) in entry.walls.iter().zip(segments) {
+                self.scene.set_wall(*wall, start, end);
+            }
+        } else {
+            for wall in entry.walls.drain(..) {
+                self.scene.remove_wall(wall);
+            }
+            entry.walls = segments
+                .into_iter()
+                .map(|(start, end)| self.scene.add_line_no_graphics(start, end))
+                .collect();
+        }
+
+        let old = std::mem::replace(&mut entry.occluder, occluder.clone());
+        self.mark_lights_near(&old);
+        self.mark_lights_near(&occluder);
+        true
+    }
+
+    pub fn remove_occluder(&mut self, id: OccluderId) -> Option<Occluder> {
+        let old = self.occluders.remove(id.0)?;
+        for wall in old.walls {
+            self.scene.remove_wall(wall);
+        }
+        self.mark_lights_near(&old.occluder);
+        Some(old.occluder)
+    }
+
+    pub fn occluder(&self, id: OccluderId) -> Option<&Occluder> {
+        self.occluders.get(id.0).map(|entry| &entry.occluder)
+    }
+
+    /// The walls every occluder has been turned into.
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn add_light(&mut self, light: Light) -> LightId {
+        let visibility = VisibilityPolygon {
+            origin: light.position(),
+            vertices: Vec::new(),
+        };
+        LightId(self.lights.insert(WorldLight {
+            light,
+            visibility,
+            dirty: true,
+        }))
+    }
+
+    /// Replaces a light. Returns false if the handle no longer points to
+    /// anything.
+    pub fn update_light(&mut self, id: LightId, light: Light) -> bool {
+        match self.lights.get_mut(id.0) {
+            Some(entry) => {
+                entry.light = light;
+                entry.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_light(&mut self, id: LightId, position: Point) -> bool {
+        match self.lights.get_mut(id.0) {
+            Some(entry) => {
+                entry.light.move_to(position);
+                entry.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Turns a light to face `direction`, in radians.
+    pub fn aim_light(&mut self, id: LightId, direction: f32) -> bool {
+        match self.lights.get_mut(id.0) {
+            Some(entry) => {
+                entry.light.set_direction(direction);
+                entry.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_light(&mut self, id: LightId) -> Option<Light> {
+        self.lights.remove(id.0).map(|entry| entry.light)
+    }
+
+    pub fn light(&self, id: LightId) -> Option<&Light> {
+        self.lights.get(id.0).map(|entry| &entry.light)
+    }
+
+    pub fn is_dirty(&self, id: LightId) -> bool {
+        self.lights.get(id.0).is_some_and(|entry| entry.dirty)
+    }
+
+    /// Recomputes the visibility of every dirty light, returning how many
+    /// lights had to be recomputed.
+    pub fn update(&mut self) -> usize {
+        let mut recomputed = 0;
+        for (_, entry) in self.lights.iter_mut().filter(|(_, entry)| entry.dirty) {
+            entry.visibility = self.scene.light_visibility(&entry.light);
+            entry.dirty = false;
+            recomputed += 1;
+        }
+        recomputed
+    }
+
+    /// The visibility polygon of a light as of the last call to
+    /// [`LightingWorld::update`].
+    pub fn visibility(&self, id: LightId) -> Option<&VisibilityPolygon> {
+        self.lights.get(id.0).map(|entry| &entry.visibility)
+    }
+
+    pub fn visibility_polygons(&self) -> impl Iterator<Item = (LightId, &VisibilityPolygon)> {
+        self.lights
+            .iter()
+            .map(|(index, entry)| (LightId(index), &entry.visibility))
+    }
+
+    fn mark_lights_near(&mut self, occluder: &Occluder) {
+        let bounds = match Bounds::around(&occluder.points) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        for (_, entry) in self.lights.iter_mut() {
+            if entry.light.bounds().overlaps(&bounds) {
+                entry.dirty = true;
+            }
+        }
+    }
+}
+
+impl Default for LightingWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}